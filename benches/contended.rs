@@ -0,0 +1,69 @@
+//! Benchmarks with multiple threads using the same stream.
+
+use bencher::{benchmark_group, benchmark_main, black_box, Bencher};
+use frappe::Sink;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const N_THREADS: usize = 4;
+const N_STEPS: usize = 1_000;
+
+/// Creates a sink with `n` observers that add up the values received.
+fn make_sink(n: usize) -> (Sink<usize>, Arc<AtomicUsize>) {
+    let sink = Sink::new();
+    let total = Arc::new(AtomicUsize::new(0));
+    for _ in 0..n {
+        let total = total.clone();
+        sink.stream().observe(move |x| {
+            total.fetch_add(*x, Ordering::Relaxed);
+        });
+    }
+    (sink, total)
+}
+
+/// All the threads send values into the same sink.
+fn contended_send(b: &mut Bencher) {
+    let (sink, total) = make_sink(10);
+    b.iter(|| {
+        thread::scope(|s| {
+            for _ in 0..N_THREADS {
+                s.spawn(|| {
+                    for k in 0..N_STEPS {
+                        sink.send(k);
+                    }
+                });
+            }
+        })
+    });
+    black_box(total.load(Ordering::Relaxed));
+}
+
+/// Half of the threads send values, while the other half adds observers that are removed after
+/// the first value they receive.
+fn contended_send_push(b: &mut Bencher) {
+    let (sink, total) = make_sink(10);
+    let stream = sink.stream();
+    b.iter(|| {
+        thread::scope(|s| {
+            for _ in 0..N_THREADS / 2 {
+                s.spawn(|| {
+                    for k in 0..N_STEPS {
+                        sink.send(k);
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..N_STEPS {
+                        stream.observe(|_| false);
+                    }
+                });
+            }
+        });
+        // removes the observers added after the last value
+        sink.send(0);
+    });
+    black_box(total.load(Ordering::Relaxed));
+}
+
+benchmark_group!(contended, contended_send, contended_send_push);
+benchmark_main!(contended);
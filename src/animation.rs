@@ -0,0 +1,177 @@
+//! Easing curves and interpolation for animated signals.
+//!
+//! `Signal::animate_to` produces a signal that moves smoothly towards every value received from a
+//! stream. The shape of the movement is given by an `Easing` curve, and the values in between are
+//! computed with the `Interpolate` trait.
+//!
+//! # Example
+//! ```
+//! use frappe::{Signal, Sink};
+//! use frappe::animation::Easing;
+//! use frappe::time::VirtualClock;
+//! use std::time::Duration;
+//!
+//! let clock = VirtualClock::new();
+//! let sink = Sink::new();
+//! let pos = Signal::constant(0.0).animate_to(
+//!     &sink.stream(),
+//!     Duration::from_secs(1),
+//!     Easing::Linear,
+//!     clock.clone(),
+//! );
+//!
+//! sink.send(10.0);
+//! clock.advance(Duration::from_millis(500));
+//! assert_eq!(pos.sample(), 5.0);
+//! clock.advance(Duration::from_secs(1));
+//! assert_eq!(pos.sample(), 10.0);
+//! ```
+
+/// Easing curves that map the animation progress to the interpolation factor.
+#[derive(Debug, Clone, Copy)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Quadratic, accelerating from zero.
+    QuadIn,
+    /// Quadratic, decelerating to zero.
+    QuadOut,
+    /// Quadratic, accelerating until halfway and then decelerating.
+    QuadInOut,
+    /// Cubic, accelerating from zero.
+    CubicIn,
+    /// Cubic, decelerating to zero.
+    CubicOut,
+    /// Cubic, accelerating until halfway and then decelerating.
+    CubicInOut,
+    /// A custom curve that maps `[0, 1]` to the interpolation factor.
+    Custom(fn(f64) -> f64),
+}
+
+impl Easing {
+    /// Applies the curve to the animation progress.
+    ///
+    /// The progress is clamped to the `[0, 1]` range.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2.0 - t),
+            Easing::QuadInOut if t < 0.5 => 2.0 * t * t,
+            Easing::QuadInOut => {
+                let u = 1.0 - t;
+                1.0 - 2.0 * u * u
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => {
+                let u = 1.0 - t;
+                1.0 - u * u * u
+            }
+            Easing::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::CubicInOut => {
+                let u = 1.0 - t;
+                1.0 - 4.0 * u * u * u
+            }
+            Easing::Custom(f) => f(t),
+        }
+    }
+}
+
+impl Default for Easing {
+    #[inline]
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+/// A value that can be blended with another one.
+///
+/// Implemented for floats, bytes, tuples and arrays, so colors can be animated as `[u8; 4]` or
+/// `(f32, f32, f32)` values.
+pub trait Interpolate {
+    /// Returns a value between `self` (at `t = 0`) and `other` (at `t = 1`).
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    #[inline]
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for f32 {
+    #[inline]
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        f64::from(*self).interpolate(&f64::from(*other), t) as f32
+    }
+}
+
+impl Interpolate for u8 {
+    /// Rounds to the nearest value, saturating at the type bounds.
+    #[inline]
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        (f64::from(*self).interpolate(&f64::from(*other), t) + 0.5) as u8
+    }
+}
+
+impl<T: Interpolate, const N: usize> Interpolate for [T; N] {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        core::array::from_fn(|i| self[i].interpolate(&other[i], t))
+    }
+}
+
+macro_rules! impl_interpolate_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: Interpolate),+> Interpolate for ($($t,)+) {
+            #[inline]
+            fn interpolate(&self, other: &Self, t: f64) -> Self {
+                ($(self.$i.interpolate(&other.$i, t),)+)
+            }
+        }
+    };
+}
+
+impl_interpolate_tuple!(A 0, B 1);
+impl_interpolate_tuple!(A 0, B 1, C 2);
+impl_interpolate_tuple!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_curves() {
+        let curves = [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+        ];
+        for e in &curves {
+            assert_eq!(e.apply(0.0), 0.0);
+            assert_eq!(e.apply(1.0), 1.0);
+            assert_eq!(e.apply(2.0), 1.0);
+        }
+        assert_eq!(Easing::QuadIn.apply(0.5), 0.25);
+        assert_eq!(Easing::QuadOut.apply(0.5), 0.75);
+        assert_eq!(Easing::CubicInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::Custom(|t| 1.0 - t).apply(0.25), 0.75);
+    }
+
+    #[test]
+    fn interpolate_values() {
+        assert_eq!(1.0f64.interpolate(&3.0, 0.5), 2.0);
+        assert_eq!(0u8.interpolate(&255, 0.5), 128);
+        assert_eq!(200u8.interpolate(&100, 1.5), 50);
+        assert_eq!((0.0f32, 10u8).interpolate(&(1.0, 20), 0.25), (0.25, 13));
+        assert_eq!(
+            [255u8, 0, 0, 255].interpolate(&[0, 0, 255, 255], 0.5),
+            [128, 0, 128, 255]
+        );
+    }
+}
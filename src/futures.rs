@@ -1,12 +1,47 @@
 //! Futures integration.
 
+use crate::signal::Signal;
 use crate::stream::Stream;
 use crate::sync::Mutex;
-use std::future::Future;
-use std::mem;
-use std::pin::Pin;
-use std::sync::Arc;
-use std::task::{Context, Poll, Waker};
+use crate::types::DropWatch;
+#[cfg(feature = "std")]
+use crate::types::Watch;
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+#[cfg(feature = "std")]
+use std::task::Wake;
+#[cfg(feature = "std")]
+use std::thread::{self, Thread};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A boxed future that can be sent to an executor.
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// An executor that runs futures in the background.
+///
+/// This is implemented for closures that take a `BoxFuture`, so any executor can be used by
+/// wrapping it's spawn function.
+pub trait Executor: Send + Sync {
+    /// Spawns a future.
+    fn spawn(&self, future: BoxFuture);
+}
+
+impl<F> Executor for F
+where
+    F: Fn(BoxFuture) + Send + Sync,
+{
+    #[inline]
+    fn spawn(&self, future: BoxFuture) {
+        self(future)
+    }
+}
 
 /// The state a stream future.
 #[derive(Debug)]
@@ -34,7 +69,8 @@ impl<T> Default for StreamFutureStorage<T> {
 
 /// A future that waits for a stream value.
 ///
-/// This is created by `Stream::next`.
+/// This is created by `Stream::next`, `Stream::first_where`, `Stream::find`, `Stream::find_map`
+/// and `Stream::collect_n`.
 #[derive(Debug)]
 pub struct StreamFuture<T> {
     storage: Arc<Mutex<StreamFutureStorage<T>>>,
@@ -52,6 +88,17 @@ impl<T: Clone + Send + 'static> StreamFuture<T> {
         this
     }
 
+    /// Creates a future that is already resolved with the supplied value.
+    pub(crate) fn ready(stream: Stream<T>, value: T) -> Self {
+        StreamFuture {
+            storage: Arc::new(Mutex::new(StreamFutureStorage {
+                value: FutureValue::Ready(value),
+                waker: None,
+            })),
+            stream,
+        }
+    }
+
     /// Registers the stream observer that will update this future.
     fn register_callback(&self) {
         let weak = Arc::downgrade(&self.storage);
@@ -113,10 +160,256 @@ impl<T> Future for StreamFuture<T> {
 
 impl<T> Unpin for StreamFuture<T> {}
 
+/// The storage of a `LastFuture`.
+#[derive(Debug)]
+struct LastStorage<T> {
+    value: Option<T>,
+    ended: bool,
+    waker: Option<Waker>,
+}
+
+/// A future that waits for a stream to end, and returns the last value sent to it.
+///
+/// This is created by `Stream::last`.
+#[derive(Debug)]
+pub struct LastFuture<T> {
+    storage: Arc<Mutex<LastStorage<T>>>,
+    stream: Stream<T>,
+}
+
+impl<T: Clone + Send + 'static> LastFuture<T> {
+    /// Creates a future that returns the last value sent to this stream.
+    pub(crate) fn new(stream: Stream<T>) -> Self {
+        let storage = Arc::new(Mutex::new(LastStorage {
+            value: None,
+            ended: false,
+            waker: None,
+        }));
+        // the stream drops it's callbacks when it ends, and that resolves the future
+        let ended = LastEnded(Arc::downgrade(&storage));
+        let weak = Arc::downgrade(&storage);
+        stream.observe(move |val| {
+            let _ended = &ended;
+            weak.upgrade()
+                .map(|st| st.lock().value = Some(val.into_owned()))
+                .is_some()
+        });
+        LastFuture { storage, stream }
+    }
+}
+
+impl<T> LastFuture<T> {
+    /// Obtains the source stream.
+    pub fn get_source(&self) -> &Stream<T> {
+        &self.stream
+    }
+}
+
+impl<T> Future for LastFuture<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        let mut storage = self.storage.lock();
+        if storage.ended {
+            Poll::Ready(storage.value.take())
+        } else {
+            storage.waker = Some(ctx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Unpin for LastFuture<T> {}
+
+/// Resolves a `LastFuture` when dropped along with the stream callback.
+struct LastEnded<T>(Weak<Mutex<LastStorage<T>>>);
+
+impl<T> Drop for LastEnded<T> {
+    fn drop(&mut self) {
+        if let Some(st) = self.0.upgrade() {
+            let mut storage = st.lock();
+            storage.ended = true;
+            let waker = storage.waker.take();
+            drop(storage);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A future that waits for a signal value that matches a predicate.
+///
+/// This is created by `Signal::wait_for`.
+pub struct SignalFuture<T, F> {
+    signal: Signal<T>,
+    pred: F,
+}
+
+impl<T, F> SignalFuture<T, F> {
+    /// Creates a future that waits until the signal value matches the predicate.
+    pub(crate) fn new(signal: Signal<T>, pred: F) -> Self {
+        SignalFuture { signal, pred }
+    }
+
+    /// Obtains the source signal.
+    pub fn get_source(&self) -> &Signal<T> {
+        &self.signal
+    }
+}
+
+impl<T, F> Future for SignalFuture<T, F>
+where
+    F: Fn(&T) -> bool,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        // register before sampling, so we don't miss a change that happens in between
+        self.signal.watch(ctx.waker());
+        let value = self.signal.sample();
+        if (self.pred)(&value) {
+            Poll::Ready(value)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T, F> Unpin for SignalFuture<T, F> {}
+
+impl<T, F> fmt::Debug for SignalFuture<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalFuture")
+            .field("signal", &self.signal)
+            .finish()
+    }
+}
+
+/// A future that resolves when a stream's consumers are gone.
+///
+/// This is created by `Sender::closed` and `CancelToken::cancelled`.
+pub struct Closed {
+    target: Weak<dyn DropWatch>,
+}
+
+impl Closed {
+    pub(crate) fn new(target: Weak<dyn DropWatch>) -> Self {
+        Closed { target }
+    }
+}
+
+impl Future for Closed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.target.upgrade() {
+            // if this was the last reference, dropping it wakes the task right away
+            Some(target) => {
+                target.watch_drop(cx.waker());
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+impl fmt::Debug for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closed({})", self.target.strong_count() == 0)
+    }
+}
+
+/// Waker that unparks a thread.
+#[cfg(feature = "std")]
+struct ThreadWaker(Thread);
+
+#[cfg(feature = "std")]
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls a future on the current thread until it's ready or the timeout expires.
+#[cfg(feature = "std")]
+pub(crate) fn block_on_timeout<F>(mut future: F, timeout: Duration) -> Option<F::Output>
+where
+    F: Future + Unpin,
+{
+    thread_local! {
+        // a single waker per thread, so the watchers recognize it and don't register it again
+        static WAKER: Waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    }
+    let deadline = Instant::now() + timeout;
+    let waker = WAKER.with(Waker::clone);
+    let mut ctx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(value) = Pin::new(&mut future).poll(&mut ctx) {
+            return Some(value);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        thread::park_timeout(deadline - now);
+        // the thread also wakes up when the timeout expires, don't poll again after that
+        if Instant::now() >= deadline {
+            return None;
+        }
+    }
+}
+
+/// How often a `PollTimer` wakes the tasks watching it.
+#[cfg(feature = "std")]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wakes the tasks watching it periodically.
+///
+/// This is used by the sources that can't notify their changes, so the futures waiting on them
+/// check for new values on a timer. The timer thread only runs while there are tasks watching.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub(crate) struct PollTimer {
+    // the registered wakers, and if the timer thread is running
+    state: Arc<Mutex<(Vec<Waker>, bool)>>,
+}
+
+#[cfg(feature = "std")]
+impl Watch for PollTimer {
+    fn watch(&self, waker: &Waker) {
+        let mut state = self.state.lock();
+        let (wakers, running) = &mut *state;
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        if !*running {
+            *running = true;
+            let state = self.state.clone();
+            thread::spawn(move || loop {
+                thread::sleep(POLL_INTERVAL);
+                let wakers = {
+                    let mut state = state.lock();
+                    if state.0.is_empty() {
+                        // nobody is watching anymore
+                        state.1 = false;
+                        return;
+                    }
+                    mem::take(&mut state.0)
+                };
+                for waker in wakers {
+                    waker.wake();
+                }
+            });
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::stream::Sink;
+    use crate::types::MaybeOwned;
     use futures::executor::block_on;
 
     #[test]
@@ -152,4 +445,88 @@ mod tests {
         sink.send(13);
         assert_eq!(block_on(&mut future), 13);
     }
+
+    #[test]
+    fn find() {
+        let sink = Sink::new();
+        let mut future = sink.stream().find(|a| a % 2 == 0);
+
+        sink.feed([1, 3, 42, 5, 13]);
+        assert_eq!(block_on(&mut future), 42);
+
+        future.reload();
+        sink.feed([7, 8]);
+        assert_eq!(block_on(&mut future), 8);
+    }
+
+    #[test]
+    fn first_where() {
+        let sink = Sink::new();
+        let future = sink.stream().first_where(|s: &&str| s.len() > 1);
+
+        sink.feed(vec!["a", "bc", "def"]);
+        assert_eq!(block_on(future), "bc");
+    }
+
+    #[test]
+    fn find_map() {
+        let sink = Sink::new();
+        let future = sink
+            .stream()
+            .find_map(|s: MaybeOwned<'_, &str>| s.parse::<i32>().ok());
+
+        sink.feed(vec!["a", "b", "12", "13"]);
+        assert_eq!(block_on(future), 12);
+    }
+
+    #[test]
+    fn collect_n() {
+        let sink = Sink::new();
+        let future1 = sink.stream().collect_n(3);
+        let future2 = sink.stream().collect_n(0);
+
+        sink.feed([1, 2, 3, 4, 5]);
+        assert_eq!(block_on(future1), [1, 2, 3]);
+        assert_eq!(block_on(future2), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn last() {
+        let sink = Sink::new();
+        let future1 = sink.stream().take(3).last();
+        let future2 = sink.stream().map(|x| *x * 2).last();
+        let future3 = sink.stream().filter(|x| *x > 10).last();
+
+        sink.feed([1, 2, 3, 4]);
+        assert_eq!(block_on(future1), Some(3));
+        // the other streams end when the sink is dropped
+        drop(sink);
+        assert_eq!(block_on(future2), Some(8));
+        assert_eq!(block_on(future3), None);
+    }
+
+    #[test]
+    fn last_take_until() {
+        let sink = Sink::new();
+        let stop = Sink::new();
+        let future = sink.stream().take_until(&stop.stream()).last();
+
+        sink.feed([1, 2]);
+        stop.send(());
+        sink.send(3);
+        assert_eq!(block_on(future), Some(2));
+    }
+
+    #[test]
+    fn last_ended() {
+        use crate::stream::BehaviorSink;
+
+        let sink = BehaviorSink::new(1);
+        let stream = sink.stream();
+        sink.send(2);
+        drop(sink);
+        // a stream that already ended still replays it's values
+        assert_eq!(block_on(stream.last()), Some(2));
+        assert_eq!(block_on(stream.map(|x| *x).last()), None);
+    }
 }
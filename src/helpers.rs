@@ -1,6 +1,7 @@
 //! Internal helper functions.
 
-use std::sync::{self as arc, Arc};
+use alloc::rc::{self, Rc};
+use alloc::sync::{self as arc, Arc};
 
 pub fn arc_and_weak<T>(val: T) -> (Arc<T>, arc::Weak<T>) {
     let rc = Arc::new(val);
@@ -8,6 +9,12 @@ pub fn arc_and_weak<T>(val: T) -> (Arc<T>, arc::Weak<T>) {
     (rc, weak)
 }
 
+pub fn rc_and_weak<T>(val: T) -> (Rc<T>, rc::Weak<T>) {
+    let rc = Rc::new(val);
+    let weak = Rc::downgrade(&rc);
+    (rc, weak)
+}
+
 macro_rules! with_weak {
     ($weak:expr, $f:expr) => {
         $weak.upgrade().map($f).is_some()
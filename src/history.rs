@@ -0,0 +1,215 @@
+//! Undo/redo history for accumulated values.
+//!
+//! `Stream::fold_with_history` folds a stream like `Stream::fold`, but it also keeps the previous
+//! states so the edits can be undone and redone. The history can be driven with the `undo` and
+//! `redo` sinks, and it's state observed with signals.
+//!
+//! # Example
+//! ```
+//! use frappe::Sink;
+//!
+//! let edits = Sink::new();
+//! let history = edits.stream().fold_with_history(String::new(), |s, c| s + *c, 100);
+//! let text = history.present();
+//!
+//! edits.feed(vec!["a", "b", "c"]);
+//! assert_eq!(text.sample(), "abc");
+//! history.undo();
+//! history.undo();
+//! assert_eq!(text.sample(), "a");
+//! history.redo();
+//! assert_eq!(text.sample(), "ab");
+//! assert!(history.can_redo().sample());
+//! ```
+
+use crate::signal::Signal;
+use crate::stream::Sink;
+use crate::time::Clock;
+use crate::types::Storage;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+use core::mem;
+use core::time::Duration;
+
+/// The states stored by a `History`.
+pub(crate) struct Timeline<A> {
+    past: VecDeque<A>,
+    present: A,
+    future: Vec<A>,
+    capacity: usize,
+    coalesce: Option<(Duration, Box<dyn Clock>)>,
+    last_edit: Option<Duration>,
+}
+
+impl<A> Timeline<A> {
+    pub(crate) fn new(initial: A, capacity: usize) -> Self {
+        Timeline {
+            past: VecDeque::new(),
+            present: initial,
+            future: Vec::new(),
+            capacity,
+            coalesce: None,
+            last_edit: None,
+        }
+    }
+
+    /// Applies an edit, saving the previous state unless it's coalesced with the last edit.
+    pub(crate) fn edit<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(A) -> A,
+        A: Clone,
+    {
+        let now = self.coalesce.as_ref().map(|(_, clock)| clock.now());
+        let coalesced = match (&self.coalesce, now, self.last_edit) {
+            (Some((window, _)), Some(now), Some(last)) => now.saturating_sub(last) <= *window,
+            _ => false,
+        };
+        if !coalesced && self.capacity > 0 {
+            if self.past.len() == self.capacity {
+                self.past.pop_front();
+            }
+            self.past.push_back(self.present.clone());
+        }
+        self.future.clear();
+        self.last_edit = now;
+        self.present = f(self.present);
+        self
+    }
+
+    fn undo(&mut self) {
+        if let Some(prev) = self.past.pop_back() {
+            self.future.push(mem::replace(&mut self.present, prev));
+            self.last_edit = None;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(next) = self.future.pop() {
+            self.past.push_back(mem::replace(&mut self.present, next));
+            self.last_edit = None;
+        }
+    }
+}
+
+/// An accumulated value that keeps it's previous states.
+///
+/// This is created by `Stream::fold_with_history`.
+pub struct History<A> {
+    timeline: Arc<Storage<Timeline<A>>>,
+    source: Arc<dyn Any + Send + Sync>,
+    undo: Sink<()>,
+    redo: Sink<()>,
+}
+
+impl<A: Clone + Send + Sync + 'static> History<A> {
+    /// Creates a history from it's shared timeline and the stream that feeds it.
+    pub(crate) fn new(
+        timeline: Arc<Storage<Timeline<A>>>,
+        source: Arc<dyn Any + Send + Sync>,
+    ) -> Self {
+        let undo = Sink::new();
+        let redo = Sink::new();
+        let weak = Arc::downgrade(&timeline);
+        undo.stream()
+            .observe(move |_| with_weak!(weak, |tl| tl.modify(Timeline::undo)));
+        let weak = Arc::downgrade(&timeline);
+        redo.stream()
+            .observe(move |_| with_weak!(weak, |tl| tl.modify(Timeline::redo)));
+        History {
+            timeline,
+            source,
+            undo,
+            redo,
+        }
+    }
+
+    /// Merges the edits that happen within `window` of the previous one into a single undo step.
+    ///
+    /// The time is read from `clock`. Undoing or redoing always ends the current group.
+    pub fn coalesce<C>(self, window: Duration, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.timeline.modify(|tl| {
+            tl.coalesce = Some((window, Box::new(clock)));
+            tl.last_edit = None;
+        });
+        self
+    }
+
+    /// Creates a signal that reads the present state.
+    pub fn present(&self) -> Signal<A> {
+        self.signal(|tl| tl.present.clone())
+    }
+
+    /// Creates a signal that tells if there are states to undo.
+    pub fn can_undo(&self) -> Signal<bool> {
+        self.signal(|tl| !tl.past.is_empty())
+    }
+
+    /// Creates a signal that tells if there are states to redo.
+    pub fn can_redo(&self) -> Signal<bool> {
+        self.signal(|tl| !tl.future.is_empty())
+    }
+
+    /// Returns a sink that undoes the last edit every time it receives a value.
+    #[inline]
+    pub fn undo_sink(&self) -> Sink<()> {
+        self.undo.clone()
+    }
+
+    /// Returns a sink that redoes the last undone edit every time it receives a value.
+    #[inline]
+    pub fn redo_sink(&self) -> Sink<()> {
+        self.redo.clone()
+    }
+
+    /// Undoes the last edit.
+    #[inline]
+    pub fn undo(&self) {
+        self.undo.send(())
+    }
+
+    /// Redoes the last undone edit.
+    #[inline]
+    pub fn redo(&self) {
+        self.redo.send(())
+    }
+
+    fn signal<R, F>(&self, f: F) -> Signal<R>
+    where
+        F: Fn(&Timeline<A>) -> R + Send + Sync + 'static,
+    {
+        let timeline = self.timeline.clone();
+        let source = self.source.clone();
+        Signal::from_fn_watch(
+            move || {
+                let _keepalive = &source;
+                timeline.with(&f)
+            },
+            Some(self.timeline.clone()),
+        )
+    }
+}
+
+impl<A> Clone for History<A> {
+    /// Creates a copy of this history that references the same states.
+    fn clone(&self) -> Self {
+        History {
+            timeline: self.timeline.clone(),
+            source: self.source.clone(),
+            undo: self.undo.clone(),
+            redo: self.redo.clone(),
+        }
+    }
+}
+
+impl<A> fmt::Debug for History<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "History(Timeline@{:p})", self.timeline)
+    }
+}
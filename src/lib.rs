@@ -4,16 +4,45 @@
 //! Rust-idiomatic way to write interactive applications in a declarative way.
 //!
 //! See each module documentation for more details.
+//!
+//! # `no_std` support
+//! The stream core can run without the standard library by disabling the default `std` feature
+//! and enabling `spin`, that provides the lock implementation. It still requires `alloc`.
+//! The parts that depend on threads or the system clock are only available with `std`.
+//!
+//! Without `std`, `types::MaybeOwned` is a minimal copy of the type instead of the one from the
+//! `maybe-owned` crate, since that crate requires the standard library.
+//!
+//! # Migrating from 0.4
+//! Before 0.5 the standard library was always used. Builds that disable the default features now
+//! have to enable `std` to keep working like before:
+//!
+//! ```toml
+//! frappe = { version = "0.5", default-features = false, features = ["std"] }
+//! ```
 #![warn(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 #[macro_use]
 mod helpers;
+pub mod animation;
 pub mod futures;
+pub mod history;
 mod lift;
+pub mod local;
 pub mod signal;
+pub mod signal_map;
+pub mod signal_vec;
+pub mod state_machine;
+pub mod store;
 pub mod stream;
 mod sync;
+pub mod time;
 pub mod types;
+pub mod var;
 
 pub use crate::signal::Signal;
-pub use crate::stream::{Sink, Stream};
+pub use crate::stream::{BehaviorSink, ReplaySink, Sink, Stream};
+pub use crate::var::Var;
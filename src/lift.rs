@@ -1,4 +1,4 @@
-//! Utilities for lifting functions into signals.
+//! Utilities for lifting functions into signals and streams.
 
 /// Maps a function over the value of signals.
 ///
@@ -56,8 +56,135 @@ macro_rules! signal_lift {
     });
 }
 
+/// Maps a function over the value of signals, propagating the sampling errors.
+///
+/// This is the fallible version of `signal_lift!`. It samples the input signals using
+/// `Signal::try_sample`, and produces a `TrySignal<R>` that returns the first error found
+/// instead of panicking.
+///
+/// # Example
+/// ```
+/// use frappe::{Signal, Sink, try_signal_lift};
+/// use frappe::signal::SampleError;
+/// use std::panic::{self, AssertUnwindSafe};
+///
+/// let sink = Sink::new();
+/// let sig1 = Signal::constant(40);
+/// let sig2 = sink.stream().fold(0, |_, n| {
+///     assert!(*n >= 0, "negative value");
+///     *n
+/// });
+/// let lifted = try_signal_lift!(sig1, sig2 => |a, b| a + b);
+///
+/// sink.send(2);
+/// assert_eq!(lifted.sample(), Ok(42));
+/// // the panic leaves the fold without a value
+/// panic::catch_unwind(AssertUnwindSafe(|| sink.send(-1))).unwrap_err();
+/// assert_eq!(lifted.sample(), Err(SampleError::EmptyStorage));
+/// ```
+#[macro_export]
+macro_rules! try_signal_lift {
+    ($sig:expr => $f:expr) => {
+        $crate::Signal::map_ok(&$crate::Signal::try_signal(&$sig), $f)
+    };
+
+    ($($sig:expr),+ => | $($args:pat),+ | $body:expr) => {
+        $crate::try_signal_lift!(@closure $body; $($args)+ ,; $($sig),+)
+    };
+
+    ($($sig:expr),+ => $f:expr) => ({
+        let f = $f;
+        $crate::try_signal_lift!(@expr f;; $($sig),+)
+    });
+
+    (@sample $var:ident) => {
+        match $crate::Signal::try_sample(&$var) {
+            Ok(val) => val,
+            Err(err) => return Err(err),
+        }
+    };
+
+    (@closure $body:expr ; $($args:pat)* , $($vars:ident)* ;) => {
+        $crate::Signal::from_fn(move || {
+            let ($($args),*) = ($($crate::try_signal_lift!(@sample $vars)),*);
+            Ok($body)
+        })
+    };
+
+    (@closure $body:expr ; $($args:pat)* , $($vars:ident)* ; $sig:expr $(,$stail:expr)*) => ({
+        let sig = $sig;
+        $crate::try_signal_lift!(@closure $body; $($args)* , $($vars)* sig ; $($stail),*)
+    });
+
+    (@expr $f:expr ; $($vars:ident)* ;) => {
+        $crate::Signal::from_fn(move || Ok($f($($crate::try_signal_lift!(@sample $vars)),*)))
+    };
+
+    (@expr $f:expr ; $($vars:ident)* ; $sig:expr $(,$stail:expr)*) => ({
+        let sig = $sig;
+        $crate::try_signal_lift!(@expr $f ; $($vars)* sig ; $($stail),*)
+    });
+}
+
+/// Samples multiple signals every time a stream fires.
+///
+/// This is the multi-signal version of `Signal::snapshot`. It converts a trigger `Stream<T>`, the
+/// signals `Signal<A>, Signal<B>, ...` and a function `Fn(MaybeOwned<T>, A, B, ...) -> R` into a
+/// `Stream<R>` that samples all the signals and calls the function on every trigger event.
+///
+/// It works with both the thread-safe types and the ones in `frappe::local`.
+///
+/// # Example
+/// ```
+/// use frappe::{Sink, Signal, snapshot};
+///
+/// let clicks = Sink::new();
+/// let name = Signal::constant("button");
+/// let count = clicks.stream().fold(0, |n, _| n + 1);
+///
+/// let log = snapshot!(clicks.stream(), name, count => |pos, name, n| format!("{} {} at {}", name, n, *pos));
+/// let last = log.hold(String::new());
+///
+/// clicks.send(4);
+/// clicks.send(7);
+/// assert_eq!(last.sample(), "button 2 at 7");
+/// ```
+#[macro_export]
+macro_rules! snapshot {
+    ($trigger:expr, $($sig:expr),+ => | $targ:pat, $($args:pat),+ | $body:expr) => {
+        $crate::snapshot!(@closure $trigger; $targ, $body; $($args)+ ,; $($sig),+)
+    };
+
+    ($trigger:expr, $($sig:expr),+ => $f:expr) => ({
+        let f = $f;
+        $crate::snapshot!(@expr $trigger; f;; $($sig),+)
+    });
+
+    (@closure $trigger:expr ; $targ:pat , $body:expr ; $($args:pat)* , $($vars:ident)* ;) => {
+        $trigger.map(move |$targ| {
+            let ($($args),*) = ($($vars.sample()),*);
+            $body
+        })
+    };
+
+    (@closure $trigger:expr ; $targ:pat , $body:expr ; $($args:pat)* , $($vars:ident)* ; $sig:expr $(,$stail:expr)*) => ({
+        let sig = $sig;
+        $crate::snapshot!(@closure $trigger; $targ, $body; $($args)* , $($vars)* sig ; $($stail),*)
+    });
+
+    (@expr $trigger:expr ; $f:expr ; $($vars:ident)* ;) => {
+        $trigger.map(move |t| $f(t, $($vars.sample()),*))
+    };
+
+    (@expr $trigger:expr ; $f:expr ; $($vars:ident)* ; $sig:expr $(,$stail:expr)*) => ({
+        let sig = $sig;
+        $crate::snapshot!(@expr $trigger; $f ; $($vars)* sig ; $($stail),*)
+    });
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::types::MaybeOwned;
     use crate::{Signal, Sink};
 
     #[test]
@@ -114,4 +241,81 @@ mod tests {
         sink1.send((10, 5));
         assert_eq!(res.sample(), "107");
     }
+
+    #[test]
+    fn try_signal_lift_closure_expr() {
+        use crate::signal::SampleError;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let sink = Sink::new();
+        let sig1 = sink.stream().fold(0, |a, n| {
+            assert!(*n >= 0);
+            a + *n
+        });
+        let sig2 = Signal::constant(2);
+        let closure = try_signal_lift!(sig1.clone(), sig2.clone() => |a, b| a * b);
+        let expr = try_signal_lift!(sig2, sig1.clone() => i32::max);
+        let single = try_signal_lift!(sig1 => |a| a + 1);
+
+        sink.send(3);
+        assert_eq!(closure.sample(), Ok(6));
+        assert_eq!(expr.sample(), Ok(3));
+        assert_eq!(single.sample(), Ok(4));
+
+        // the panic leaves the fold storage empty
+        let res = panic::catch_unwind(AssertUnwindSafe(|| sink.send(-1)));
+        assert!(res.is_err());
+        assert_eq!(closure.sample(), Err(SampleError::EmptyStorage));
+        assert_eq!(expr.sample(), Err(SampleError::EmptyStorage));
+        assert_eq!(single.sample(), Err(SampleError::EmptyStorage));
+    }
+
+    #[test]
+    fn snapshot_closure() {
+        let trigger = Sink::new();
+        let sink = Sink::new();
+        let sig1 = sink.stream().hold(1);
+        let sig2 = Signal::constant((2, 3));
+        let res = snapshot!(trigger.stream(), sig1, sig2 => |t, a, (b, c)| *t + a + b + c)
+            .collect::<Vec<_>>();
+
+        trigger.send(10);
+        sink.send(20);
+        trigger.send(100);
+        assert_eq!(res.sample(), [16, 125]);
+    }
+
+    #[test]
+    fn snapshot_expr() {
+        fn sum(t: MaybeOwned<'_, i32>, a: i32, b: i32) -> i32 {
+            *t + a + b
+        }
+
+        let trigger = Sink::new();
+        let sig1 = Signal::constant(1);
+        let res = snapshot!(trigger.stream(), sig1.clone(), sig1.map(|x| x * 10) => sum).hold(0);
+
+        trigger.send(100);
+        assert_eq!(res.sample(), 111);
+    }
+
+    #[test]
+    fn snapshot_local() {
+        use crate::local;
+        use alloc::rc::Rc;
+
+        let trigger = local::Sink::new();
+        let sink = local::Sink::new();
+        // not `Send`, so only the local types can capture it
+        let name = Rc::new("n");
+        let sig1 = sink.stream().hold(1);
+        let sig2 = local::Signal::constant(2);
+        let res =
+            snapshot!(trigger.stream(), sig1, sig2 => |t, a, b| format!("{}={}", name, *t + a + b))
+                .hold(String::new());
+
+        sink.send(10);
+        trigger.send(100);
+        assert_eq!(res.sample(), "n=112");
+    }
 }
@@ -0,0 +1,38 @@
+//! Single-threaded versions of the Stream and Signal types.
+//!
+//! The types in this module have the core API of the ones in the crate root, but they're backed
+//! by `Rc`/`RefCell` instead of `Arc` and locks. Closures don't need to be `Send` or `Sync`, so
+//! they can capture objects that are bound to a single thread, like GUI widgets.
+//!
+//! The resulting objects can't be sent to another thread. The operators that work with threads,
+//! futures or storage change notifications (like `Stream::map_async`, `Stream::pausable_buffered`
+//! or `Signal::wait_for`), the replaying sinks, the fallible signal operations and the history
+//! are only available on the thread-safe types. The `snapshot!` macro works with both.
+//!
+//! # Example
+//! ```
+//! use frappe::local::Sink;
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! // this isn't `Send`, so it can't be captured by the thread-safe types
+//! let log = Rc::new(RefCell::new(Vec::new()));
+//! let log_ = log.clone();
+//!
+//! let sink = Sink::new();
+//! let doubled = sink.stream().map(|x| *x * 2);
+//! doubled.observe(move |x| log_.borrow_mut().push(*x));
+//! let sum = sink.stream().fold(0, |a, x| a + *x);
+//!
+//! sink.feed(vec![1, 2, 3]);
+//! assert_eq!(*log.borrow(), [2, 4, 6]);
+//! assert_eq!(sum.sample(), 6);
+//! ```
+
+mod callbacks;
+mod signal;
+mod storage;
+mod stream;
+
+pub use self::signal::Signal;
+pub use self::stream::{Sender, Sink, Stream};
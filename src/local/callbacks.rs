@@ -0,0 +1,139 @@
+//! Callback container for the single-threaded Stream.
+
+use crate::types::MaybeOwned;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+/// Boxed callback closure stored inside a `FnCell`.
+type CallbackFn<T> = Box<dyn Fn(MaybeOwned<'_, T>) -> bool>;
+
+/// Function that becomes uncallable after it returns false.
+struct FnCell<T> {
+    f: CallbackFn<T>,
+    alive: Cell<bool>,
+}
+
+impl<T> FnCell<T> {
+    /// Creates a new `FnCell` from the supplied closure.
+    fn new<F>(f: F) -> Self
+    where
+        F: Fn(MaybeOwned<'_, T>) -> bool + 'static,
+    {
+        FnCell {
+            f: Box::new(f),
+            alive: Cell::new(true),
+        }
+    }
+
+    /// Calls the stored function and updates it's callable status.
+    fn call(&self, arg: MaybeOwned<'_, T>) -> bool {
+        if self.alive.get() {
+            let is_alive = (self.f)(arg);
+            self.alive.set(is_alive);
+            is_alive
+        } else {
+            false
+        }
+    }
+}
+
+impl<T> fmt::Debug for FnCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FnCell {{ f: Fn@{:p}, alive: {:?} }}",
+            self.f, self.alive
+        )
+    }
+}
+
+/// A collection of callbacks.
+///
+/// Senders call a snapshot of the list, and it's only copied when it's modified while being
+/// called, so callbacks can be added or removed from inside a callback.
+pub struct Callbacks<T> {
+    fs: RefCell<Rc<Vec<Rc<FnCell<T>>>>>,
+}
+
+impl<T> Callbacks<T> {
+    /// Creates an empty callback list.
+    pub fn new() -> Self {
+        Callbacks {
+            fs: Default::default(),
+        }
+    }
+
+    /// Adds a new closure to the callback list.
+    pub fn push<F>(&self, cb: F)
+    where
+        F: Fn(MaybeOwned<'_, T>) -> bool + 'static,
+    {
+        Rc::make_mut(&mut self.fs.borrow_mut()).push(Rc::new(FnCell::new(cb)))
+    }
+
+    /// Sends an owned value.
+    ///
+    /// This sends a ref to the first N-1 callbacks, and the owned value to the last.
+    pub fn call_owned(&self, arg: T) {
+        let fs = self.fs.borrow().clone();
+        let mut all_alive = true;
+        if let Some((last, rest)) = fs.split_last() {
+            for f in rest {
+                all_alive &= f.call(MaybeOwned::Borrowed(&arg));
+            }
+            all_alive &= last.call(MaybeOwned::Owned(arg));
+        }
+        if !all_alive {
+            self.cleanup();
+        }
+    }
+
+    /// Sends a value by reference.
+    pub fn call_ref(&self, arg: &T) {
+        let fs = self.fs.borrow().clone();
+        let all_alive = fs
+            .iter()
+            .map(|f| f.call(MaybeOwned::Borrowed(arg)))
+            .fold(true, |a, alive| a & alive);
+        if !all_alive {
+            self.cleanup();
+        }
+    }
+
+    /// Sends a value.
+    #[inline]
+    pub fn call<'a>(&self, arg: impl Into<MaybeOwned<'a, T>>)
+    where
+        T: 'a,
+    {
+        match arg.into() {
+            MaybeOwned::Owned(v) => self.call_owned(v),
+            MaybeOwned::Borrowed(r) => self.call_ref(r),
+        }
+    }
+
+    /// Removes the dead callbacks.
+    fn cleanup(&self) {
+        Rc::make_mut(&mut self.fs.borrow_mut()).retain(|f| f.alive.get())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.fs.borrow().len()
+    }
+}
+
+impl<T> fmt::Debug for Callbacks<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callbacks").field("fs", &self.fs).finish()
+    }
+}
+
+impl<T> Default for Callbacks<T> {
+    fn default() -> Self {
+        Callbacks::new()
+    }
+}
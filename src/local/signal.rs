@@ -0,0 +1,191 @@
+//! The single-threaded Signal type.
+
+use crate::local::storage::Storage;
+use crate::local::stream::Stream;
+use crate::types::MaybeOwned;
+use alloc::rc::Rc;
+use core::cell::OnceCell;
+use core::fmt;
+
+/// Represents a value that changes over time.
+pub struct Signal<T> {
+    f: Rc<dyn Fn() -> T>,
+}
+
+impl<T> Signal<T> {
+    /// Creates a signal with constant value.
+    pub fn constant(val: T) -> Self
+    where
+        T: Clone + 'static,
+    {
+        Signal::from_fn(move || val.clone())
+    }
+
+    /// Creates a signal that samples it's values from an external source.
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn() -> T + 'static,
+    {
+        Signal { f: Rc::new(f) }
+    }
+
+    /// Creates a signal from shared storage.
+    pub(crate) fn from_storage<S>(storage: Rc<Storage<T>>, source: S) -> Self
+    where
+        T: Clone + 'static,
+        S: 'static,
+    {
+        Signal::from_fn(move || {
+            let _keepalive = &source;
+            storage.get()
+        })
+    }
+
+    /// Samples the value of the signal.
+    #[inline]
+    pub fn sample(&self) -> T {
+        (self.f)()
+    }
+
+    /// Maps a signal with the provided function.
+    pub fn map<F, R>(&self, f: F) -> Signal<R>
+    where
+        F: Fn(T) -> R + 'static,
+        T: 'static,
+    {
+        let this = self.clone();
+        Signal::from_fn(move || f(this.sample()))
+    }
+
+    /// Folds a signal using the provided function.
+    ///
+    /// The fold operation applies a `Fn(A, T) -> A` function on the signal every time it's sampled,
+    /// where `A` is the current accumulator value and `T` is the value of the input signal.
+    pub fn fold<A, F>(&self, initial: A, f: F) -> Signal<A>
+    where
+        F: Fn(A, T) -> A + 'static,
+        T: 'static,
+        A: Clone + 'static,
+    {
+        let this = self.clone();
+        let storage = Storage::new(initial);
+        Signal::from_fn(move || {
+            let val = this.sample();
+            storage.replace_fetch(|acc| f(acc, val))
+        })
+    }
+
+    /// Samples the value of this signal every time the trigger stream fires.
+    pub fn snapshot<S, F, R>(&self, trigger: &Stream<S>, f: F) -> Stream<R>
+    where
+        F: Fn(T, MaybeOwned<'_, S>) -> R + 'static,
+        T: 'static,
+        S: 'static,
+        R: 'static,
+    {
+        let this = self.clone();
+        trigger.map(move |t| f(this.sample(), t))
+    }
+
+    /// Creates a signal with a cyclic definition.
+    ///
+    /// The closure receives a forward declaration of a signal that must be used to construct the
+    /// final Signal. Sampling the forward-declared signal inside the closure will cause a panic.
+    pub fn cyclic<F>(definition: F) -> Self
+    where
+        F: FnOnce(&Signal<T>) -> Signal<T>,
+        T: 'static,
+    {
+        let cell = Rc::new(OnceCell::new());
+        let cell_ = cell.clone();
+        let sig = Signal::from_fn(move || {
+            Signal::sample(cell_.get().expect("sampled forward-declared Signal"))
+        });
+        let _ = cell.set(definition(&sig));
+        sig
+    }
+}
+
+impl<T: 'static> Signal<Signal<T>> {
+    /// Creates a new signal that samples the inner value of a nested signal.
+    pub fn switch(&self) -> Signal<T> {
+        let this = self.clone();
+        Signal::from_fn(move || this.sample().sample())
+    }
+}
+
+impl<T> Clone for Signal<T> {
+    /// Creates a new signal that references the same value.
+    fn clone(&self) -> Self {
+        Signal { f: self.f.clone() }
+    }
+}
+
+impl<T: Default + 'static> Default for Signal<T> {
+    /// Creates a constant signal with T's default value.
+    #[inline]
+    fn default() -> Self {
+        Signal::from_fn(T::default)
+    }
+}
+
+impl<T: Clone + 'static> From<T> for Signal<T> {
+    /// Creates a constant signal from T.
+    #[inline]
+    fn from(val: T) -> Self {
+        Signal::constant(val)
+    }
+}
+
+impl<T> fmt::Debug for Signal<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signal(Fn@{:p})", self.f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Signal<T> {
+    /// Samples the signal and formats the value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.sample(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::Sink;
+    use core::cell::Cell;
+
+    #[test]
+    fn local_signal_basic() {
+        // Rc isn't Send, so it can't be captured by a thread-safe signal
+        let counter = Rc::new(Cell::new(0));
+        let counter_ = counter.clone();
+        let sig = Signal::from_fn(move || {
+            counter_.set(counter_.get() + 1);
+            counter_.get()
+        });
+        let double = sig.map(|x| x * 2);
+        let acc = sig.fold(0, |a, x| a + x);
+
+        assert_eq!(double.sample(), 2);
+        assert_eq!(acc.sample(), 2);
+        assert_eq!(counter.get(), 2);
+        assert_eq!(Signal::constant(Rc::new(5)).map(|x| *x).sample(), 5);
+    }
+
+    #[test]
+    fn local_signal_cyclic() {
+        let sink = Sink::new();
+        let sig = Signal::cyclic(|fwd| {
+            let fwd = fwd.clone();
+            sink.stream()
+                .map(move |n: MaybeOwned<'_, i32>| fwd.sample() + *n)
+                .hold(0)
+        });
+
+        sink.send(1);
+        sink.send(2);
+        assert_eq!(sig.sample(), 3);
+    }
+}
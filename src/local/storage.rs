@@ -0,0 +1,69 @@
+//! Storage cell used by the single-threaded Signal.
+
+use core::cell::RefCell;
+
+/// Storage cell for shared signal values.
+///
+/// The cell is never borrowed while running a closure. `Storage::replace` and
+/// `Storage::replace_fetch` leave it empty while their closure runs, so sampling it from there
+/// panics with "storage empty". `Storage::replace_clone` keeps the value, so it's closure can
+/// sample the signals that read from it.
+#[derive(Debug)]
+pub struct Storage<T> {
+    val: RefCell<Option<T>>,
+}
+
+const ERR_EMPTY: &str = "storage empty";
+
+impl<T> Storage<T> {
+    /// Creates a storage with an initial value.
+    pub fn new(val: T) -> Self {
+        Storage {
+            val: RefCell::new(Some(val)),
+        }
+    }
+
+    /// Gets the value by cloning.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.val.borrow().clone().expect(ERR_EMPTY)
+    }
+
+    /// Sets the value.
+    pub fn set(&self, val: T) {
+        *self.val.borrow_mut() = Some(val);
+    }
+
+    /// Maps the stored value, leaving the storage empty while the closure runs.
+    pub fn replace<F>(&self, f: F)
+    where
+        F: FnOnce(T) -> T,
+    {
+        let old = self.val.borrow_mut().take().expect(ERR_EMPTY);
+        self.set(f(old));
+    }
+
+    /// Same as `replace` but it also returns the new value.
+    pub fn replace_fetch<F>(&self, f: F) -> T
+    where
+        F: FnOnce(T) -> T,
+        T: Clone,
+    {
+        let old = self.val.borrow_mut().take().expect(ERR_EMPTY);
+        let new = f(old);
+        self.set(new.clone());
+        new
+    }
+
+    /// A `replace` version with cloning.
+    pub fn replace_clone<F>(&self, f: F)
+    where
+        F: FnOnce(T) -> T,
+        T: Clone,
+    {
+        let old = self.get();
+        self.set(f(old));
+    }
+}
@@ -0,0 +1,1088 @@
+//! The single-threaded Stream type.
+
+use crate::helpers::rc_and_weak;
+use crate::local::callbacks::Callbacks;
+use crate::local::signal::Signal;
+use crate::local::storage::Storage;
+use crate::types::{MaybeOwned, ObserveResult, SumType2};
+use alloc::collections::VecDeque;
+use alloc::rc::{Rc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::ops::{Bound, RangeBounds};
+
+#[cfg(feature = "either")]
+use crate::types::Either;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+/// A source of events that feeds the streams connected to it.
+#[derive(Debug)]
+pub struct Sink<T> {
+    cbs: Rc<Callbacks<T>>,
+}
+
+impl<T> Sink<T> {
+    /// Creates a new sink.
+    pub fn new() -> Self {
+        Sink {
+            cbs: Default::default(),
+        }
+    }
+
+    /// Creates a stream that receives the events sent to this sink.
+    pub fn stream(&self) -> Stream<T> {
+        Stream::new(self.cbs.clone(), Source::None)
+    }
+
+    /// Sends a value into the sink.
+    ///
+    /// The value will be distributed `N-1` times as reference and then one time by value,
+    /// where `N` is the amount of streams connected to this sink.
+    #[inline]
+    pub fn send<'a>(&self, val: impl Into<MaybeOwned<'a, T>>)
+    where
+        T: 'a,
+    {
+        self.cbs.call(val)
+    }
+
+    /// Sends multiple values into the sink.
+    #[inline]
+    pub fn feed<'a, I, U>(&self, iter: I)
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<MaybeOwned<'a, T>>,
+        T: 'a,
+    {
+        for val in iter {
+            self.send(val)
+        }
+    }
+}
+
+impl<T> Default for Sink<T> {
+    /// Creates a new sink.
+    #[inline]
+    fn default() -> Self {
+        Sink::new()
+    }
+}
+
+impl<T> Clone for Sink<T> {
+    /// Creates a copy of this sink that references the same event source.
+    fn clone(&self) -> Self {
+        Sink {
+            cbs: self.cbs.clone(),
+        }
+    }
+}
+
+/// The source object of a Stream.
+///
+/// This is used to create a strong reference to a parent stream.
+#[derive(Clone)]
+enum Source {
+    /// No source.
+    None,
+    /// The source is a type-erased object. Usually a stream of a different type.
+    Erased(Rc<dyn Any>),
+}
+
+impl Source {
+    fn stream<T: 'static>(s: &Stream<T>) -> Self {
+        Source::Erased(Rc::new(s.clone()))
+    }
+
+    fn stream2<A: 'static, B: 'static>(s1: &Stream<A>, s2: &Stream<B>) -> Self {
+        Source::Erased(Rc::new((s1.clone(), s2.clone())))
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::None => write!(f, "None"),
+            Source::Erased(s) => write!(f, "Erased(Any@{:p})", s),
+        }
+    }
+}
+
+/// A stream of discrete events sent over time.
+#[derive(Debug)]
+pub struct Stream<T> {
+    cbs: Rc<Callbacks<T>>,
+    source: Source,
+}
+
+impl<T> Stream<T> {
+    /// Creates a stream from it's components.
+    fn new(cbs: Rc<Callbacks<T>>, source: Source) -> Self {
+        Stream { cbs, source }
+    }
+
+    /// Creates a stream that never fires.
+    pub fn never() -> Self {
+        Stream::new(Default::default(), Source::None)
+    }
+
+    /// Reads the values from the stream.
+    ///
+    /// The closure will be dropped when it returns a false-y value (see `ObserveResult`) or when
+    /// the source stream is dropped.
+    pub fn observe<F, R>(&self, f: F)
+    where
+        F: Fn(MaybeOwned<'_, T>) -> R + 'static,
+        R: ObserveResult,
+    {
+        self.cbs.push(move |arg| f(arg).is_callback_alive());
+    }
+
+    /// Observes the stream while keeping a reference to it.
+    ///
+    /// # Warning
+    /// This creates a cyclic `Rc` reference that can only be broken by the closure signaling it's
+    /// deletion (via `ObserveResult`), so if the closure never unregisters itself it will leak memory.
+    pub fn observe_strong<F, R>(&self, f: F)
+    where
+        F: Fn(MaybeOwned<'_, T>) -> R + 'static,
+        T: 'static,
+        R: ObserveResult,
+    {
+        let this = self.clone();
+        self.cbs.push(move |arg| {
+            let _keepalive = &this;
+            f(arg).is_callback_alive()
+        });
+    }
+
+    /// Observes the stream on behalf of an owner object that is referenced weakly.
+    ///
+    /// The closure will be dropped on the first event received after the owner is dropped.
+    pub fn observe_weak<O, F, R>(&self, owner: &Rc<O>, f: F)
+    where
+        O: 'static,
+        F: Fn(&O, MaybeOwned<'_, T>) -> R + 'static,
+        R: ObserveResult,
+    {
+        let weak = Rc::downgrade(owner);
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|owner| f(&owner, arg).is_callback_alive())
+                .unwrap_or(false)
+        });
+    }
+
+    /// Chainable version of `Stream::observe`.
+    #[inline]
+    pub fn inspect<F, R>(self, f: F) -> Self
+    where
+        F: Fn(MaybeOwned<'_, T>) -> R + 'static,
+        R: ObserveResult,
+    {
+        self.observe(f);
+        self
+    }
+}
+
+impl<T: 'static> Stream<T> {
+    /// Maps this stream into another stream using the provided function.
+    #[inline]
+    pub fn map<F, R>(&self, f: F) -> Stream<R>
+    where
+        F: Fn(MaybeOwned<'_, T>) -> R + 'static,
+        R: 'static,
+    {
+        self.filter_map(move |arg| Some(f(arg)))
+    }
+
+    /// Creates a new stream that only contains the values where the predicate is `true`.
+    pub fn filter<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| if pred(&arg) {
+                cb.call(arg)
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Does filter and map on a stream simultaneously.
+    pub fn filter_map<F, R>(&self, f: F) -> Stream<R>
+    where
+        F: Fn(MaybeOwned<'_, T>) -> Option<R> + 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| if let Some(val) = f(arg) {
+                cb.call(val)
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Creates a new stream that fires with the events from both streams.
+    pub fn merge(&self, other: &Stream<T>) -> Self {
+        let (new_cbs, weak1) = rc_and_weak(Callbacks::new());
+        let weak2 = weak1.clone();
+        self.cbs
+            .push(move |arg| with_weak!(weak1, |cb| cb.call(arg)));
+        other
+            .cbs
+            .push(move |arg| with_weak!(weak2, |cb| cb.call(arg)));
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Merges two streams of different types using two functions.
+    pub fn merge_with<U, F1, F2, R>(&self, other: &Stream<U>, f1: F1, f2: F2) -> Stream<R>
+    where
+        F1: Fn(MaybeOwned<'_, T>) -> R + 'static,
+        F2: Fn(MaybeOwned<'_, U>) -> R + 'static,
+        U: 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak1) = rc_and_weak(Callbacks::new());
+        let weak2 = weak1.clone();
+        self.cbs
+            .push(move |arg| with_weak!(weak1, |cb| cb.call(f1(arg))));
+        other
+            .cbs
+            .push(move |arg| with_weak!(weak2, |cb| cb.call(f2(arg))));
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Merges two streams of different types using a single function that takes an `Either` argument.
+    #[cfg(feature = "either")]
+    #[inline]
+    pub fn merge_with_either<U, F, R>(&self, other: &Stream<U>, f: F) -> Stream<R>
+    where
+        F: Fn(Either<MaybeOwned<'_, T>, MaybeOwned<'_, U>>) -> R + Clone + 'static,
+        U: 'static,
+        R: 'static,
+    {
+        let f_ = f.clone();
+        self.merge_with(
+            other,
+            move |a| f(Either::Left(a)),
+            move |b| f_(Either::Right(b)),
+        )
+    }
+
+    /// Accumulates the values sent over this stream.
+    ///
+    /// The accumulator is moved out of the storage while the closure runs, so if it panics any
+    /// later sampling attempt will panic too. Use `Stream::fold_clone` to avoid this.
+    pub fn fold<A, F>(&self, initial: A, f: F) -> Signal<A>
+    where
+        F: Fn(A, MaybeOwned<'_, T>) -> A + 'static,
+        A: Clone + 'static,
+    {
+        let (storage, weak) = rc_and_weak(Storage::new(initial));
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |st| {
+                st.replace(|old| f(old, arg));
+            })
+        });
+        Signal::from_storage(storage, self.clone())
+    }
+
+    /// Folds the stream by cloning the accumulator.
+    pub fn fold_clone<A, F>(&self, initial: A, f: F) -> Signal<A>
+    where
+        F: Fn(A, MaybeOwned<'_, T>) -> A + 'static,
+        A: Clone + 'static,
+    {
+        let (storage, weak) = rc_and_weak(Storage::new(initial));
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |st| {
+                st.replace_clone(|old| f(old, arg));
+            })
+        });
+        Signal::from_storage(storage, self.clone())
+    }
+
+    /// Maps each stream event to `0..N` output values using the provided Sender.
+    pub fn map_n<F, R>(&self, f: F) -> Stream<R>
+    where
+        F: Fn(MaybeOwned<'_, T>, Sender<R>) + 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        self.cbs
+            .push(move |arg| with_weak!(weak, |cb| f(arg, Sender::new(cb))));
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Folds the stream and returns the accumulator values as a stream.
+    pub fn scan<A, F>(&self, initial: A, f: F) -> Stream<A>
+    where
+        F: Fn(A, MaybeOwned<'_, T>) -> A + 'static,
+        A: Clone + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let storage = Storage::new(initial);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                let new = storage.replace_fetch(|old| f(old, arg));
+                cb.call(new)
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Folds the stream and returns `0..N` output values.
+    pub fn scan_n<A, F, R>(&self, initial: A, f: F) -> Stream<R>
+    where
+        F: Fn(A, MaybeOwned<'_, T>, Sender<R>) -> A + 'static,
+        A: 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let storage = Storage::new(initial);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| storage.replace(|old| f(
+                old,
+                arg,
+                Sender::new(cb)
+            )))
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Creates a collection from the values sent to this stream.
+    #[inline]
+    pub fn collect<C>(&self) -> Signal<C>
+    where
+        C: Default + Extend<T> + Clone + 'static,
+        T: Clone,
+    {
+        self.fold(C::default(), |mut a, v| {
+            a.extend(Some(v.into_owned()));
+            a
+        })
+    }
+
+    /// Returns a stream that contains only the Nth value from the input stream.
+    #[inline]
+    pub fn element_at(&self, index: usize) -> Self {
+        self.elements_between(index..=index)
+    }
+
+    /// Returns a stream that contains the values with index in the specified range.
+    pub fn elements_between<B>(&self, range: B) -> Self
+    where
+        B: RangeBounds<usize> + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let pos = Cell::new(0);
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|cb| {
+                    let cur_pos = pos.get();
+                    pos.set(cur_pos + 1);
+                    let after_start = match range.start_bound() {
+                        Bound::Included(s) => cur_pos >= *s,
+                        Bound::Excluded(s) => cur_pos > *s,
+                        Bound::Unbounded => true,
+                    };
+                    let before_end = match range.end_bound() {
+                        Bound::Included(e) => cur_pos <= *e,
+                        Bound::Excluded(e) => cur_pos < *e,
+                        Bound::Unbounded => true,
+                    };
+                    if after_start && before_end {
+                        cb.call(arg)
+                    }
+                    before_end // drop the callback after we're past the end
+                })
+                .unwrap_or(false)
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that contains only the first `n` values from the input stream.
+    pub fn take(&self, n: usize) -> Self {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let pos = Cell::new(0);
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|cb| {
+                    let cur_pos = pos.get();
+                    pos.set(cur_pos + 1);
+                    if cur_pos < n {
+                        cb.call(arg);
+                    }
+                    cur_pos + 1 < n // drop the callback after the last value
+                })
+                .unwrap_or(false)
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that skips the first `n` values from the input stream.
+    #[inline]
+    pub fn skip(&self, n: usize) -> Self {
+        self.elements_between(n..)
+    }
+
+    /// Returns a stream that contains every `step`th value, starting with the first one.
+    ///
+    /// # Panics
+    /// Panics if `step` is zero.
+    pub fn step_by(&self, step: usize) -> Self {
+        assert!(step != 0, "step_by: step must be non-zero");
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let pos = Cell::new(0);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                let cur_pos = pos.get();
+                pos.set(cur_pos + 1);
+                // `usize::is_multiple_of` is too recent to use here
+                #[allow(clippy::manual_is_multiple_of)]
+                let send = cur_pos % step == 0;
+                if send {
+                    cb.call(arg)
+                }
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that contains the values until the predicate returns `false`.
+    pub fn take_while<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|cb| {
+                    let keep = pred(&arg);
+                    if keep {
+                        cb.call(arg);
+                    }
+                    keep // drop the callback on the first rejected value
+                })
+                .unwrap_or(false)
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that skips the values until the predicate returns `false`.
+    pub fn skip_while<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let skipping = Cell::new(true);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                if skipping.get() {
+                    if pred(&arg) {
+                        return;
+                    }
+                    skipping.set(false);
+                }
+                cb.call(arg)
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that contains the values until `other` fires.
+    pub fn take_until<U: 'static>(&self, other: &Stream<U>) -> Self {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let stopped = Rc::new(Cell::new(false));
+        let stopped_ = stopped.clone();
+        self.cbs.push(move |arg| {
+            if stopped.get() {
+                return false;
+            }
+            with_weak!(weak, |cb| cb.call(arg))
+        });
+        other.cbs.push(move |_| {
+            stopped_.set(true);
+            false
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Returns a stream that skips the values until `other` fires.
+    pub fn skip_until<U: 'static>(&self, other: &Stream<U>) -> Self {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let started = Rc::new(Cell::new(false));
+        let started_ = started.clone();
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| if started.get() {
+                cb.call(arg)
+            })
+        });
+        other.cbs.push(move |_| {
+            started_.set(true);
+            false
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Creates a stream that only lets values through while the signal is `true`.
+    ///
+    /// The signal is sampled on every value received, and the values sent while it's `false` are
+    /// discarded.
+    pub fn gate(&self, open: &Signal<bool>) -> Self {
+        let open = open.clone();
+        self.filter(move |_| open.sample())
+    }
+
+    /// Splits this stream into sub-streams grouped by key.
+    ///
+    /// Every time a value with a new key is received, a `(key, stream)` pair is sent to the
+    /// output stream, and then the value is sent to the new group stream. Later values with the
+    /// same key are sent to that group.
+    ///
+    /// A group expires when all of it's streams are dropped, so the next value with that key will
+    /// create a new group.
+    #[cfg(feature = "std")]
+    pub fn group_by<K, F>(&self, key_fn: F) -> Stream<(K, Stream<T>)>
+    where
+        F: Fn(&T) -> K + 'static,
+        K: Hash + Eq + Clone + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let groups = RefCell::new(HashMap::<K, Weak<Callbacks<T>>>::new());
+        // the output stream owns the parent reference, the groups get it from there
+        let parent: Rc<dyn Any> = Rc::new(self.clone());
+        let parent_weak = Rc::downgrade(&parent);
+        self.cbs.push(move |arg| {
+            let key = key_fn(&arg);
+            let group = groups.borrow().get(&key).and_then(Weak::upgrade);
+            if let Some(group) = group {
+                group.call(arg);
+                return true;
+            }
+            let mut groups = groups.borrow_mut();
+            // remove the expired groups before adding a new one
+            groups.retain(|_, group| group.strong_count() > 0);
+            if let (Some(cb), Some(parent)) = (weak.upgrade(), parent_weak.upgrade()) {
+                let (group, group_weak) = rc_and_weak(Callbacks::new());
+                groups.insert(key.clone(), group_weak);
+                drop(groups);
+                cb.call((key, Stream::new(group.clone(), Source::Erased(parent))));
+                group.call(arg);
+                true
+            } else {
+                // drop callback if the output stream and all the groups are dropped
+                !groups.is_empty()
+            }
+        });
+        Stream::new(new_cbs, Source::Erased(parent))
+    }
+
+    /// Splits this stream in two using a predicate.
+    ///
+    /// The values that satisfy the predicate are sent to the first stream, and the rest to the
+    /// second one. Each value is sent to only one stream, so no cloning is done.
+    pub fn partition<F>(&self, pred: F) -> (Stream<T>, Stream<T>)
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        let mut streams = self.route(2, move |val| if pred(val) { 0 } else { 1 });
+        let stream_2 = streams.pop().unwrap();
+        let stream_1 = streams.pop().unwrap();
+        (stream_1, stream_2)
+    }
+
+    /// Splits this stream into `n` streams.
+    ///
+    /// The function returns the index of the stream that receives each value. Each value is sent
+    /// to only one stream, so no cloning is done. Values with an index out of range are discarded.
+    pub fn route<F>(&self, n: usize, f: F) -> Vec<Stream<T>>
+    where
+        F: Fn(&T) -> usize + 'static,
+    {
+        let (cbs, weaks): (Vec<_>, Vec<_>) = (0..n).map(|_| rc_and_weak(Callbacks::new())).unzip();
+        self.cbs.push(move |arg| {
+            let index = f(&arg);
+            if let Some(cb) = weaks.get(index).and_then(Weak::upgrade) {
+                cb.call(arg);
+                true
+            } else {
+                // drop callback if all output streams dropped
+                weaks.iter().any(|weak| weak.strong_count() > 0)
+            }
+        });
+        let source = Source::stream(self);
+        cbs.into_iter()
+            .map(|cbs| Stream::new(cbs, source.clone()))
+            .collect()
+    }
+}
+
+impl<T: Clone + 'static> Stream<T> {
+    /// Creates a Signal that holds the last value sent to this stream.
+    #[inline]
+    pub fn hold(&self, initial: T) -> Signal<T> {
+        self.hold_if(initial, |_| true)
+    }
+
+    /// Holds the last value in this stream where the predicate is `true`.
+    pub fn hold_if<F>(&self, initial: T, pred: F) -> Signal<T>
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        let (storage, weak) = rc_and_weak(Storage::new(initial));
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |st| if pred(&arg) {
+                st.set(arg.into_owned());
+            })
+        });
+        Signal::from_storage(storage, self.clone())
+    }
+
+    /// Collects all pairs of values from two streams in chronological order.
+    #[inline]
+    pub fn zip<U>(&self, other: &Stream<U>) -> Stream<(T, U)>
+    where
+        U: Clone + 'static,
+    {
+        self.zip_with(other, |a, b| (a, b))
+    }
+
+    /// Zips two streams using a custom function.
+    pub fn zip_with<U, F, R>(&self, other: &Stream<U>, f: F) -> Stream<R>
+    where
+        F: Fn(T, U) -> R + 'static,
+        U: Clone + 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak1) = rc_and_weak(Callbacks::new());
+        let weak2 = weak1.clone();
+
+        let left = Rc::new(RefCell::new(VecDeque::new()));
+        let right = Rc::new(RefCell::new(VecDeque::new()));
+        let left1 = left.clone();
+        let right1 = right.clone();
+        let f = Rc::new(f);
+        let f_ = f.clone();
+
+        self.cbs.push(move |arg| {
+            with_weak!(weak1, |cb| {
+                let val = right1.borrow_mut().pop_front();
+                if let Some(val) = val {
+                    cb.call(f(arg.into_owned(), val));
+                } else {
+                    left.borrow_mut().push_back(arg.into_owned());
+                }
+            })
+        });
+
+        other.cbs.push(move |arg| {
+            with_weak!(weak2, |cb| {
+                let val = left1.borrow_mut().pop_front();
+                if let Some(val) = val {
+                    cb.call(f_(val, arg.into_owned()));
+                } else {
+                    right.borrow_mut().push_back(arg.into_owned());
+                }
+            })
+        });
+
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Collects pairs of values from two streams using their last value seen.
+    #[inline]
+    pub fn combine<U>(&self, other: &Stream<U>) -> Stream<(T, U)>
+    where
+        U: Clone + 'static,
+    {
+        self.combine_with(other, |a, b| (a, b))
+    }
+
+    /// Combines two streams using a custom function.
+    pub fn combine_with<U, F, R>(&self, other: &Stream<U>, f: F) -> Stream<R>
+    where
+        F: Fn(T, U) -> R + 'static,
+        U: Clone + 'static,
+        R: 'static,
+    {
+        let (new_cbs, weak1) = rc_and_weak(Callbacks::new());
+        let weak2 = weak1.clone();
+
+        let left = Rc::new(RefCell::new(None));
+        let right = Rc::new(RefCell::new(None));
+        let left1 = left.clone();
+        let right1 = right.clone();
+        let f = Rc::new(f);
+        let f_ = f.clone();
+
+        self.cbs.push(move |arg| {
+            with_weak!(weak1, |cb| {
+                let arg = arg.into_owned();
+                *left.borrow_mut() = Some(arg.clone());
+                let val = right1.borrow().clone();
+                if let Some(val) = val {
+                    cb.call(f(arg, val));
+                }
+            })
+        });
+
+        other.cbs.push(move |arg| {
+            with_weak!(weak2, |cb| {
+                let arg = arg.into_owned();
+                *right.borrow_mut() = Some(arg.clone());
+                let val = left1.borrow().clone();
+                if let Some(val) = val {
+                    cb.call(f_(val, arg));
+                }
+            })
+        });
+
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Pairs the values of this stream with the last value seen on `other`.
+    ///
+    /// Unlike `Stream::combine`, this only fires when `self` receives a value. The values received
+    /// before `other` sends it's first value are discarded.
+    pub fn with_latest_from<U>(&self, other: &Stream<U>) -> Stream<(T, U)>
+    where
+        U: Clone + 'static,
+    {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let (latest, latest_weak) = rc_and_weak(RefCell::new(None));
+        other.cbs.push(move |arg| {
+            with_weak!(latest_weak, |latest| {
+                *latest.borrow_mut() = Some(arg.into_owned());
+            })
+        });
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                let val = latest.borrow().clone();
+                if let Some(val) = val {
+                    cb.call((arg.into_owned(), val));
+                }
+            })
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+}
+
+impl<T: Clone + 'static> Stream<Option<T>> {
+    /// Filters a stream of `Option`, returning only the unwrapped `Some` values.
+    #[inline]
+    pub fn filter_some(&self) -> Stream<T> {
+        self.filter_first()
+    }
+}
+
+impl<T: Clone + 'static, E: Clone + 'static> Stream<Result<T, E>> {
+    /// Filters a stream of `Result`, returning only the unwrapped `Ok` values.
+    #[inline]
+    pub fn filter_ok(&self) -> Stream<T> {
+        self.filter_first()
+    }
+
+    /// Filters a stream of `Result`, returning only the unwrapped `Err` values.
+    #[inline]
+    pub fn filter_err(&self) -> Stream<E> {
+        self.filter_second()
+    }
+}
+
+impl<T: SumType2 + Clone + 'static> Stream<T>
+where
+    T::Type1: 'static,
+    T::Type2: 'static,
+{
+    /// Creates a stream with only the first element of a sum type.
+    pub fn filter_first(&self) -> Stream<T::Type1> {
+        self.filter_map(|res| {
+            if res.is_type1() {
+                res.into_owned().into_type1()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Creates a stream with only the second element of a sum type.
+    pub fn filter_second(&self) -> Stream<T::Type2> {
+        self.filter_map(|res| {
+            if res.is_type2() {
+                res.into_owned().into_type2()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Splits a two element sum type stream into two streams with the unwrapped values.
+    pub fn split(&self) -> (Stream<T::Type1>, Stream<T::Type2>) {
+        let (cbs_1, weak_1) = rc_and_weak(Callbacks::new());
+        let (cbs_2, weak_2) = rc_and_weak(Callbacks::new());
+        self.cbs.push(move |result| {
+            if result.is_type1() {
+                if let Some(cb) = weak_1.upgrade() {
+                    cb.call(result.into_owned().into_type1().unwrap());
+                    true
+                } else {
+                    // drop callback if both output streams dropped
+                    weak_2.upgrade().is_some()
+                }
+            } else if let Some(cb) = weak_2.upgrade() {
+                cb.call(result.into_owned().into_type2().unwrap());
+                true
+            } else {
+                weak_1.upgrade().is_some()
+            }
+        });
+        let source = Source::stream(self);
+        let stream_1 = Stream::new(cbs_1, source.clone());
+        let stream_2 = Stream::new(cbs_2, source);
+        (stream_1, stream_2)
+    }
+}
+
+impl<T: 'static> Stream<Stream<T>> {
+    /// Listens to the events from the last stream sent to a nested stream.
+    pub fn switch(&self) -> Stream<T> {
+        let (new_cbs, weak) = rc_and_weak(Callbacks::new());
+        let id = Rc::new(Cell::new(0usize)); // id of each stream sent
+        self.cbs.push(move |stream| {
+            if weak.upgrade().is_none() {
+                return false;
+            }
+            let cbs_w = weak.clone();
+            let cur_id = id.clone();
+            // increment the id so it will only send to the last stream
+            let my_id = id.get() + 1;
+            id.set(my_id);
+            // redirect the inner stream to the output stream
+            stream.cbs.push(move |arg| {
+                if my_id != cur_id.get() {
+                    return false;
+                }
+                with_weak!(cbs_w, |cb| cb.call(arg))
+            });
+            true
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+}
+
+impl<T> Clone for Stream<T> {
+    /// Creates a copy of this stream that references the same event chain.
+    fn clone(&self) -> Self {
+        Stream {
+            cbs: self.cbs.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<T> Default for Stream<T> {
+    /// Creates a stream that never fires.
+    #[inline]
+    fn default() -> Self {
+        Stream::never()
+    }
+}
+
+/// Sends values into a stream.
+///
+/// This is a restricted version of `Sink` used by `Stream::map_n` and `Stream::scan_n`.
+#[derive(Debug)]
+pub struct Sender<T>(Sink<T>);
+
+impl<T> Sender<T> {
+    /// Constructs a new Sender from a list of callbacks.
+    fn new(cbs: Rc<Callbacks<T>>) -> Self {
+        Sender(Sink { cbs })
+    }
+
+    /// Sends a value.
+    #[inline]
+    pub fn send(&self, val: T) {
+        self.0.send(val)
+    }
+
+    /// Sends multiple values.
+    #[inline]
+    pub fn feed(&self, iter: impl IntoIterator<Item = T>) {
+        self.0.feed(iter)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    /// Creates a copy of this sender that references the same event source.
+    fn clone(&self) -> Self {
+        Sender(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_stream_basic() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let log_ = log.clone();
+        let sink = Sink::new();
+        let (even, odd) = (
+            sink.stream().filter(|x| x % 2 == 0),
+            sink.stream()
+                .filter_map(|x| if *x % 2 != 0 { Some(*x * 10) } else { None }),
+        );
+        even.observe(move |x| log_.borrow_mut().push(*x));
+        let merged = even.merge(&odd).collect::<Vec<_>>();
+        let zipped = even.zip(&odd).collect::<Vec<_>>();
+        let last = sink.stream().hold(0);
+        let second = sink.stream().element_at(1).hold(0);
+
+        sink.feed(1..=4);
+        assert_eq!(*log.borrow(), [2, 4]);
+        assert_eq!(merged.sample(), [10, 2, 30, 4]);
+        assert_eq!(zipped.sample(), [(2, 10), (4, 30)]);
+        assert_eq!(last.sample(), 4);
+        assert_eq!(second.sample(), 2);
+    }
+
+    #[test]
+    fn local_stream_gate_latest() {
+        let sink = Sink::new();
+        let other = Sink::new();
+        let open = sink.stream().map(|x| *x % 2 == 0).hold(false);
+        let gated = sink.stream().gate(&open).collect::<Vec<_>>();
+        let latest = sink
+            .stream()
+            .with_latest_from(&other.stream())
+            .collect::<Vec<_>>();
+
+        sink.send(1);
+        other.send("a");
+        sink.feed(vec![2, 4, 5]);
+        other.send("b");
+        sink.send(8);
+        assert_eq!(gated.sample(), [2, 4, 8]);
+        assert_eq!(latest.sample(), [(2, "a"), (4, "a"), (5, "a"), (8, "b")]);
+    }
+
+    #[test]
+    fn local_stream_reentrant() {
+        let sink: Sink<i32> = Sink::new();
+        let sink_ = sink.clone();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_ = seen.clone();
+        // sends a second value and observes the stream from inside a callback
+        sink.stream().observe(move |x| {
+            if *x == 1 {
+                let seen = seen_.clone();
+                sink_.stream().observe(move |x| seen.borrow_mut().push(*x));
+                sink_.send(2);
+            }
+            *x == 1
+        });
+
+        sink.send(1);
+        sink.send(3);
+        assert_eq!(*seen.borrow(), [2, 3]);
+        assert_eq!(sink.cbs.len(), 1);
+    }
+
+    #[test]
+    fn local_stream_take_skip() {
+        let sink = Sink::new();
+        let stop = Sink::new();
+        let take = sink.stream().take(2).collect::<Vec<_>>();
+        let skip = sink.stream().skip(3).collect::<Vec<_>>();
+        let step = sink.stream().step_by(2).collect::<Vec<_>>();
+        let take_while = sink.stream().take_while(|x| *x < 3).collect::<Vec<_>>();
+        let skip_while = sink.stream().skip_while(|x| *x < 3).collect::<Vec<_>>();
+        let take_until = sink.stream().take_until(&stop.stream()).collect::<Vec<_>>();
+        let skip_until = sink.stream().skip_until(&stop.stream()).collect::<Vec<_>>();
+
+        sink.feed(0..3);
+        stop.send(());
+        sink.feed([3, 1, 4]);
+        assert_eq!(take.sample(), [0, 1]);
+        assert_eq!(skip.sample(), [3, 1, 4]);
+        assert_eq!(step.sample(), [0, 2, 1]);
+        assert_eq!(take_while.sample(), [0, 1, 2]);
+        assert_eq!(skip_while.sample(), [3, 1, 4]);
+        assert_eq!(take_until.sample(), [0, 1, 2]);
+        assert_eq!(skip_until.sample(), [3, 1, 4]);
+    }
+
+    #[test]
+    fn local_stream_split_route() {
+        let sink = Sink::new();
+        let (oks, errs) = sink.stream().split();
+        let oks = oks.collect::<Vec<i32>>();
+        let errs = errs.collect::<Vec<&str>>();
+        let nums = sink.stream().filter_ok();
+        let (even, odd) = nums.partition(|x| x % 2 == 0);
+        let (even, odd) = (even.collect::<Vec<_>>(), odd.collect::<Vec<_>>());
+        let routed: Vec<_> = nums
+            .route(3, |x| *x as usize % 4)
+            .iter()
+            .map(|s| s.collect::<Vec<_>>())
+            .collect();
+
+        sink.feed(vec![Ok(1), Err("a"), Ok(2), Ok(3), Err("b"), Ok(4)]);
+        assert_eq!(oks.sample(), [1, 2, 3, 4]);
+        assert_eq!(errs.sample(), ["a", "b"]);
+        assert_eq!(even.sample(), [2, 4]);
+        assert_eq!(odd.sample(), [1, 3]);
+        // values routed out of range are discarded
+        let routed: Vec<_> = routed.iter().map(Signal::sample).collect();
+        assert_eq!(routed, [vec![4], vec![1], vec![2]]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn local_stream_group_by() {
+        let sink: Sink<(char, i32)> = Sink::new();
+        let groups = Rc::new(RefCell::new(Vec::new()));
+        let groups_ = groups.clone();
+        let grouped = sink.stream().group_by(|(k, _)| *k);
+        grouped.observe(move |group| {
+            let (key, stream) = group.into_owned();
+            groups_
+                .borrow_mut()
+                .push((key, stream.map(|x| x.1).collect::<Vec<_>>()));
+        });
+
+        sink.feed(vec![('a', 1), ('b', 2), ('a', 3)]);
+        let result: Vec<_> = groups
+            .borrow()
+            .iter()
+            .map(|(k, s)| (*k, s.sample()))
+            .collect();
+        assert_eq!(result, [('a', vec![1, 3]), ('b', vec![2])]);
+    }
+
+    #[test]
+    fn local_stream_switch() {
+        let sink1 = Sink::new();
+        let sink2 = Sink::new();
+        let nested = Sink::new();
+        let switched = nested.stream().switch().hold(0);
+
+        nested.send(sink1.stream());
+        sink1.send(1);
+        assert_eq!(switched.sample(), 1);
+        nested.send(sink2.stream());
+        sink1.send(2);
+        sink2.send(3);
+        assert_eq!(switched.sample(), 3);
+    }
+}
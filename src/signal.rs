@@ -25,17 +25,60 @@
 //! assert_eq!(sig2.sample(), 42);
 //! ```
 
+use crate::animation::{Easing, Interpolate};
+use crate::futures::SignalFuture;
 use crate::stream::Stream;
 use crate::sync::Mutex;
-use crate::types::{MaybeOwned, Storage};
-use std::fmt;
-use std::sync::{mpsc, Arc};
+use crate::time::{Clock, VectorSpace};
+use crate::types::{MaybeOwned, Storage, Watch};
+use alloc::sync::Arc;
+use core::fmt;
+use core::task::Waker;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::futures::{block_on_timeout, PollTimer};
+#[cfg(feature = "std")]
+use std::sync::mpsc;
 
 #[cfg(feature = "lazycell")]
 use lazycell::AtomicLazyCell;
 
 /// Represents a value that changes over time.
-pub struct Signal<T>(Arc<dyn Fn() -> T + Send + Sync>);
+pub struct Signal<T> {
+    f: Arc<dyn Fn() -> Result<T, SampleError> + Send + Sync>,
+    watch: Option<Arc<dyn Watch>>,
+}
+
+/// A signal whose value can fail to be computed.
+///
+/// The error defaults to `SampleError`, that is the one returned by `Signal::try_signal`.
+pub type TrySignal<T, E = SampleError> = Signal<Result<T, E>>;
+
+/// The reasons why a signal can't be sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleError {
+    /// The storage that holds the signal value is empty.
+    ///
+    /// This happens after a closure that was updating it (like the one passed to
+    /// `Stream::fold`) panics.
+    EmptyStorage,
+    /// A forward-declared signal from `Signal::cyclic` was sampled before it's definition was
+    /// complete.
+    UninitializedCycle,
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleError::EmptyStorage => f.write_str("storage empty"),
+            SampleError::UninitializedCycle => f.write_str("sampled forward-declared Signal"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SampleError {}
 
 impl<T> Signal<T> {
     /// Creates a signal with constant value.
@@ -51,7 +94,26 @@ impl<T> Signal<T> {
     where
         F: Fn() -> T + Send + Sync + 'static,
     {
-        Signal(Arc::new(f))
+        Signal::from_fn_watch(f, None)
+    }
+
+    /// Creates a signal from a function and an object that notifies it's changes.
+    pub(crate) fn from_fn_watch<F>(f: F, watch: Option<Arc<dyn Watch>>) -> Self
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        Signal::from_try_fn_watch(move || Ok(f()), watch)
+    }
+
+    /// Creates a signal from a fallible function and an object that notifies it's changes.
+    pub(crate) fn from_try_fn_watch<F>(f: F, watch: Option<Arc<dyn Watch>>) -> Self
+    where
+        F: Fn() -> Result<T, SampleError> + Send + Sync + 'static,
+    {
+        Signal {
+            f: Arc::new(f),
+            watch,
+        }
     }
 
     /// Creates a signal from shared storage.
@@ -60,19 +122,59 @@ impl<T> Signal<T> {
         T: Clone + Send + Sync + 'static,
         S: Send + Sync + 'static,
     {
-        Signal::from_fn(move || {
-            let _keepalive = &source;
-            storage.get()
-        })
+        let watch = storage.clone();
+        Signal::from_try_fn_watch(
+            move || {
+                let _keepalive = &source;
+                storage.try_get()
+            },
+            Some(watch),
+        )
     }
 
     /// Samples the value of the signal.
     ///
     /// The action of sampling pulls the value through the signal chain until it finds it's source,
     /// clones it if necessary, and then transforms it into the result value.
+    ///
+    /// # Panics
+    /// Panics if the value can't be computed. See `Signal::try_sample` for the non-panicking
+    /// version.
     #[inline]
     pub fn sample(&self) -> T {
-        self.0()
+        match (self.f)() {
+            Ok(val) => val,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Samples the value of the signal, returning an error if it can't be computed.
+    ///
+    /// The errors are propagated by the signal operations, so this also catches the ones
+    /// produced by the signals this one was derived from. Closures that sample signals by
+    /// themselves (like the ones passed to `Signal::from_fn`) will still panic.
+    #[inline]
+    pub fn try_sample(&self) -> Result<T, SampleError> {
+        (self.f)()
+    }
+
+    /// Creates a signal that returns the sampling errors as values instead of panicking.
+    pub fn try_signal(&self) -> TrySignal<T>
+    where
+        T: 'static,
+    {
+        let this = self.clone();
+        let watch = self.watch.clone();
+        Signal::from_fn_watch(move || this.try_sample(), watch)
+    }
+
+    /// Registers a waker that will be woken when the signal's source storage changes.
+    ///
+    /// Does nothing if this signal has no storage that can notify changes.
+    pub(crate) fn watch(&self, waker: &Waker) {
+        if let Some(watch) = &self.watch {
+            watch.watch(waker);
+        }
     }
 
     /// Maps a signal using the provided function.
@@ -84,7 +186,8 @@ impl<T> Signal<T> {
         T: 'static,
     {
         let this = self.clone();
-        Signal::from_fn(move || f(this.sample()))
+        let watch = self.watch.clone();
+        Signal::from_try_fn_watch(move || this.try_sample().map(&f), watch)
     }
 
     /// Folds a signal using the provided function.
@@ -100,10 +203,111 @@ impl<T> Signal<T> {
         A: Clone + Send + Sync + 'static,
     {
         let this = self.clone();
+        let watch = self.watch.clone();
         let storage = Storage::new(initial);
+        Signal::from_try_fn_watch(
+            move || {
+                let val = this.try_sample()?;
+                storage.try_replace_fetch(|acc| f(acc, val))
+            },
+            watch,
+        )
+    }
+
+    /// Integrates the signal over time.
+    ///
+    /// Every time the resulting signal is sampled, it samples this signal and accumulates the
+    /// area since the previous sample using the trapezoidal rule. The time is read from `clock`
+    /// and measured in seconds. The integral starts at zero on the first sample.
+    pub fn integral<C>(&self, clock: C) -> Signal<T>
+    where
+        C: Clock + 'static,
+        T: VectorSpace + Clone + Send + 'static,
+    {
+        let this = self.clone();
+        let state = Mutex::new(None::<(Duration, T, T)>);
+        Signal::from_try_fn_watch(
+            move || {
+                let val = this.try_sample()?;
+                let now = clock.now();
+                let mut state = state.lock();
+                let acc = match &*state {
+                    Some((last_time, last_val, acc)) => {
+                        let dt = now.saturating_sub(*last_time).as_secs_f64();
+                        acc.add(&last_val.add(&val).scale(dt / 2.0))
+                    }
+                    None => T::zero(),
+                };
+                *state = Some((now, val, acc.clone()));
+                Ok(acc)
+            },
+            None,
+        )
+    }
+
+    /// Differentiates the signal over time.
+    ///
+    /// Every time the resulting signal is sampled, it returns the rate of change of this signal
+    /// since the previous sample, per second of `clock` time. The first sample returns zero,
+    /// and samples taken at the same instant return the previous rate.
+    pub fn derivative<C>(&self, clock: C) -> Signal<T>
+    where
+        C: Clock + 'static,
+        T: VectorSpace + Clone + Send + 'static,
+    {
+        let this = self.clone();
+        let state = Mutex::new(None::<(Duration, T, T)>);
+        Signal::from_try_fn_watch(
+            move || {
+                let val = this.try_sample()?;
+                let now = clock.now();
+                let mut state = state.lock();
+                let rate = match &*state {
+                    Some((last_time, last_val, rate)) => {
+                        if now <= *last_time {
+                            return Ok(rate.clone());
+                        }
+                        let dt = (now - *last_time).as_secs_f64();
+                        val.sub(last_val).scale(dt.recip())
+                    }
+                    None => T::zero(),
+                };
+                *state = Some((now, val, rate.clone()));
+                Ok(rate)
+            },
+            None,
+        )
+    }
+
+    /// Animates the signal towards every value received from `targets`.
+    ///
+    /// The resulting signal has the value of this signal until the first target arrives. Then it
+    /// interpolates from it's current value to the target over `duration`, following the `easing`
+    /// curve. A new target restarts the animation from wherever it is at that moment. The time is
+    /// read from `clock`.
+    pub fn animate_to<C>(
+        &self,
+        targets: &Stream<T>,
+        duration: Duration,
+        easing: Easing,
+        clock: C,
+    ) -> Signal<T>
+    where
+        C: Clock + 'static,
+        T: Interpolate + Clone + Send + Sync + 'static,
+    {
+        let anim = Arc::new(Animation {
+            initial: self.clone(),
+            tween: Mutex::new(None),
+            duration,
+            easing,
+            clock,
+        });
+        targets.observe_weak(&anim, |anim, target| anim.retarget(target.into_owned()));
+        let source = targets.clone();
         Signal::from_fn(move || {
-            let val = this.sample();
-            storage.replace_fetch(|acc| f(acc, val))
+            let _keepalive = &source;
+            anim.sample()
         })
     }
 
@@ -123,6 +327,7 @@ impl<T> Signal<T> {
     ///
     /// When sampled, the resulting signal consumes all the current values on the channel
     /// (using `try_recv`) and returns the last value seen.
+    #[cfg(feature = "std")]
     #[inline]
     pub fn from_channel(initial: T, rx: mpsc::Receiver<T>) -> Self
     where
@@ -136,25 +341,58 @@ impl<T> Signal<T> {
     /// When sampled, the resulting signal consumes all the current values on the channel
     /// (using `try_recv`) and folds them using the current signal value as the
     /// initial accumulator state.
+    ///
+    /// The channel can't notify when it receives a value, so the futures created by
+    /// `Signal::wait_for` on this signal are woken up on a timer to check for new values.
+    #[cfg(feature = "std")]
     pub fn fold_channel<V, F>(initial: T, rx: mpsc::Receiver<V>, f: F) -> Self
     where
         F: Fn(T, V) -> T + Send + Sync + 'static,
         T: Clone + Send + Sync + 'static,
         V: Send + 'static,
     {
-        let storage = Storage::new(initial);
+        let storage = Arc::new(Storage::new(initial));
         let rx = Mutex::new(rx);
-        Signal::from_fn(move || {
-            let source = rx.lock();
-            if let Ok(first) = source.try_recv() {
-                storage.replace_fetch(|old| {
-                    let acc = f(old, first);
-                    source.try_iter().fold(acc, &f)
-                })
-            } else {
-                storage.get()
-            }
-        })
+        Signal::from_try_fn_watch(
+            move || {
+                let source = rx.lock();
+                if let Ok(first) = source.try_recv() {
+                    storage.try_replace_fetch(|old| {
+                        let acc = f(old, first);
+                        source.try_iter().fold(acc, &f)
+                    })
+                } else {
+                    storage.try_get()
+                }
+            },
+            Some(Arc::new(PollTimer::default())),
+        )
+    }
+
+    /// Creates a future that resolves with the signal value once the predicate is `true`.
+    ///
+    /// The predicate is checked every time the future is polled. Signals backed by storage (like
+    /// the ones created by `Stream::hold` or `Stream::fold`, and the signals mapped from them)
+    /// wake the future when that storage is written, and the channel signals wake it on a timer.
+    /// Other signals have no way to notify their changes, so the condition is only checked when
+    /// the future is polled for other reasons.
+    pub fn wait_for<F>(&self, pred: F) -> SignalFuture<T, F>
+    where
+        F: Fn(&T) -> bool,
+    {
+        SignalFuture::new(self.clone(), pred)
+    }
+
+    /// Blocks the current thread until the predicate is `true` or the timeout expires.
+    ///
+    /// This is the blocking version of `Signal::wait_for`. Returns the signal value that matched
+    /// the predicate, or `None` if the timeout expired first.
+    #[cfg(feature = "std")]
+    pub fn wait_for_timeout<F>(&self, pred: F, timeout: Duration) -> Option<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        block_on_timeout(self.wait_for(pred), timeout)
     }
 
     /// Creates a signal with a cyclic definition.
@@ -163,35 +401,105 @@ impl<T> Signal<T> {
     /// declaration of a signal that must be used to construct the final Signal. Then this
     /// previous forward-declaration is replaced with the value returned by the closure.
     ///
-    /// Sampling the forward-declared signal will cause a panic.
+    /// Sampling the forward-declared signal inside the closure will cause a panic, or a
+    /// `SampleError::UninitializedCycle` error when using `Signal::try_sample`.
     #[cfg(feature = "lazycell")]
     pub fn cyclic<F>(definition: F) -> Self
     where
         F: FnOnce(&Signal<T>) -> Signal<T>,
         T: 'static,
     {
-        let storage = Arc::new(AtomicLazyCell::new());
+        let storage = Arc::new(AtomicLazyCell::<Signal<T>>::new());
         let st = storage.clone();
-        let sig = Signal::from_fn(move || {
-            Signal::sample(st.borrow().expect("sampled forward-declared Signal"))
-        });
+        let sig = Signal::from_try_fn_watch(
+            move || {
+                st.borrow()
+                    .ok_or(SampleError::UninitializedCycle)?
+                    .try_sample()
+            },
+            None,
+        );
         storage.fill(definition(&sig)).unwrap();
         sig
     }
 }
 
+/// State of an animated signal.
+struct Animation<T, C> {
+    initial: Signal<T>,
+    // value at the start of the animation, target value and start time
+    tween: Mutex<Option<(T, T, Duration)>>,
+    duration: Duration,
+    easing: Easing,
+    clock: C,
+}
+
+impl<T: Interpolate + Clone, C: Clock> Animation<T, C> {
+    fn value_at(&self, now: Duration) -> Option<T> {
+        let tween = self.tween.lock();
+        let (from, to, start) = tween.as_ref()?;
+        let elapsed = now.saturating_sub(*start);
+        if elapsed >= self.duration {
+            return Some(to.clone());
+        }
+        let t = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        Some(from.interpolate(to, self.easing.apply(t)))
+    }
+
+    fn sample(&self) -> T {
+        // the lock is released before sampling the initial signal
+        self.value_at(self.clock.now())
+            .unwrap_or_else(|| self.initial.sample())
+    }
+
+    fn retarget(&self, target: T) {
+        let now = self.clock.now();
+        let current = self.value_at(now).unwrap_or_else(|| self.initial.sample());
+        *self.tween.lock() = Some((current, target, now));
+    }
+}
+
 impl<T: 'static> Signal<Signal<T>> {
     /// Creates a new signal that samples the inner value of a nested signal.
     pub fn switch(&self) -> Signal<T> {
         let this = self.clone();
-        Signal::from_fn(move || this.sample().sample())
+        Signal::from_try_fn_watch(move || this.try_sample()?.try_sample(), None)
+    }
+}
+
+impl<T: 'static, E: 'static> Signal<Result<T, E>> {
+    /// Maps the successful values of a fallible signal, keeping the errors unchanged.
+    pub fn map_ok<F, R>(&self, f: F) -> TrySignal<R, E>
+    where
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        self.map(move |res| res.map(&f))
+    }
+
+    /// Maps the successful values of a fallible signal with a function that can also fail.
+    pub fn and_then<F, R>(&self, f: F) -> TrySignal<R, E>
+    where
+        F: Fn(T) -> Result<R, E> + Send + Sync + 'static,
+    {
+        self.map(move |res| res.and_then(&f))
+    }
+
+    /// Maps the errors of a fallible signal, keeping the successful values unchanged.
+    pub fn map_err<F, E2>(&self, f: F) -> TrySignal<T, E2>
+    where
+        F: Fn(E) -> E2 + Send + Sync + 'static,
+    {
+        self.map(move |res| res.map_err(&f))
     }
 }
 
 impl<T> Clone for Signal<T> {
     /// Creates a new signal that references the same value.
     fn clone(&self) -> Self {
-        Signal(self.0.clone())
+        Signal {
+            f: self.f.clone(),
+            watch: self.watch.clone(),
+        }
     }
 }
 
@@ -213,7 +521,7 @@ impl<T: Clone + Send + Sync + 'static> From<T> for Signal<T> {
 
 impl<T> fmt::Debug for Signal<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Signal(Fn@{:p})", self.0)
+        write!(f, "Signal(Fn@{:p})", self.f)
     }
 }
 
@@ -304,6 +612,7 @@ mod tests {
         assert_eq!(format!("{}", sig2), "13");
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn signal_channel() {
         let (tx, rx) = mpsc::channel();
@@ -315,4 +624,229 @@ mod tests {
 
         assert_eq!(sig.sample(), 55);
     }
+
+    #[test]
+    fn signal_integral_derivative() {
+        use crate::time::VirtualClock;
+
+        let clock = VirtualClock::new();
+        let t = {
+            let clock = clock.clone();
+            Signal::from_fn(move || clock.now().as_secs_f64())
+        };
+        // x(t) = t^2, so the derivative is 2t and the integral is t^3/3
+        let x = t.map(|t| t * t);
+        let dx = x.derivative(clock.clone());
+        let ix = x.integral(clock.clone());
+        let v = Signal::constant((1.0f32, -2.0f32)).integral(clock.clone());
+
+        assert_eq!(dx.sample(), 0.0);
+        assert_eq!(ix.sample(), 0.0);
+        assert_eq!(v.sample(), (0.0, 0.0));
+
+        for _ in 0..100 {
+            clock.advance(Duration::from_millis(10));
+            ix.sample();
+        }
+        assert!((ix.sample() - 1.0 / 3.0).abs() < 1e-3);
+        assert_eq!(v.sample(), (1.0, -2.0));
+
+        clock.set(Duration::from_secs(2));
+        dx.sample();
+        clock.advance(Duration::from_millis(1));
+        assert!((dx.sample() - 4.0).abs() < 1e-2);
+        // sampling again at the same instant keeps the previous rate
+        assert!((dx.sample() - 4.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn signal_animate_to() {
+        use crate::stream::Sink;
+        use crate::time::VirtualClock;
+
+        let clock = VirtualClock::new();
+        let sink = Sink::new();
+        let anim = Signal::constant((0.0, 0.0)).animate_to(
+            &sink.stream(),
+            Duration::from_secs(2),
+            Easing::QuadIn,
+            clock.clone(),
+        );
+        assert_eq!(anim.sample(), (0.0, 0.0));
+
+        sink.send((4.0, -8.0));
+        assert_eq!(anim.sample(), (0.0, 0.0));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(anim.sample(), (1.0, -2.0));
+
+        // retargeting starts from the current value
+        sink.send((1.0, 0.0));
+        assert_eq!(anim.sample(), (1.0, -2.0));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(anim.sample(), (1.0, 0.0));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(anim.sample(), (1.0, 0.0));
+
+        let instant = Signal::constant(0u8).animate_to(
+            &sink.stream().map(|_| 255),
+            Duration::from_secs(0),
+            Easing::Linear,
+            clock.clone(),
+        );
+        sink.send((0.0, 0.0));
+        assert_eq!(instant.sample(), 255);
+    }
+
+    #[test]
+    fn signal_try_sample() {
+        use crate::stream::Sink;
+        use std::panic::{self, AssertUnwindSafe};
+
+        let sink = Sink::new();
+        let sig = sink.stream().fold(0, |a, n: MaybeOwned<'_, i32>| {
+            assert!(*n != 0, "invalid value");
+            a + *n
+        });
+        let double = sig.map(|x| x * 2);
+        let acc = double.fold(0, |a, x| a + x);
+        let checked = double.try_signal().and_then(|x| {
+            if x < 10 {
+                Ok(x)
+            } else {
+                Err(SampleError::EmptyStorage)
+            }
+        });
+
+        sink.send(2);
+        assert_eq!(double.try_sample(), Ok(4));
+        assert_eq!(acc.try_sample(), Ok(4));
+        assert_eq!(checked.sample(), Ok(4));
+        assert_eq!(checked.map_ok(|x| x + 1).map_err(|_| "err").sample(), Ok(5));
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| sink.send(0)));
+        assert!(res.is_err());
+        assert_eq!(sig.try_sample(), Err(SampleError::EmptyStorage));
+        assert_eq!(double.try_sample(), Err(SampleError::EmptyStorage));
+        assert_eq!(acc.try_sample(), Err(SampleError::EmptyStorage));
+        assert_eq!(checked.sample(), Err(SampleError::EmptyStorage));
+        let res = panic::catch_unwind(AssertUnwindSafe(|| double.sample()));
+        assert_eq!(
+            res.unwrap_err()
+                .downcast_ref::<String>()
+                .map(|s| s.as_str()),
+            Some("storage empty")
+        );
+    }
+
+    #[cfg(feature = "lazycell")]
+    #[test]
+    fn signal_try_sample_cyclic() {
+        let sig = Signal::cyclic(|fwd| {
+            assert_eq!(fwd.try_sample(), Err(SampleError::UninitializedCycle));
+            assert_eq!(
+                fwd.map(|x: i32| x + 1).try_sample(),
+                Err(SampleError::UninitializedCycle)
+            );
+            Signal::constant(1)
+        });
+        assert_eq!(sig.try_sample(), Ok(1));
+        assert_eq!(Signal::constant(sig).switch().try_sample(), Ok(1));
+    }
+
+    #[test]
+    fn signal_wait_for() {
+        use crate::stream::Sink;
+        use futures::executor::block_on;
+        use std::thread;
+        use std::time::Duration;
+
+        let sink = Sink::new();
+        let sig = sink.stream().hold(0).map(|a| a * 2);
+        let future = sig.wait_for(|a| *a > 10);
+
+        let sink_ = sink.clone();
+        let handle = thread::spawn(move || {
+            for i in 0..10 {
+                thread::sleep(Duration::from_millis(10));
+                sink_.send(i);
+            }
+        });
+
+        assert_eq!(block_on(future), 12);
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn signal_wait_for_timeout() {
+        use crate::stream::Sink;
+        use std::thread;
+        use std::time::Duration;
+
+        let sink = Sink::new();
+        let sig = sink.stream().fold(0, |a, n| a + *n);
+        assert_eq!(
+            sig.wait_for_timeout(|a| *a > 0, Duration::from_millis(20)),
+            None
+        );
+
+        let sink_ = sink.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sink_.feed(vec![1, 2, 3]);
+        });
+
+        assert_eq!(
+            sig.wait_for_timeout(|a| *a >= 6, Duration::from_secs(5)),
+            Some(6)
+        );
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn signal_wait_for_timeout_watchers() {
+        use std::time::Duration;
+
+        let storage = Arc::new(Storage::new(0));
+        let sig = Signal::from_storage(storage.clone(), ());
+        for _ in 0..10 {
+            assert_eq!(
+                sig.wait_for_timeout(|a| *a > 0, Duration::from_millis(1)),
+                None
+            );
+        }
+        // the same thread waker is reused, so it's only registered once
+        assert_eq!(storage.watchers(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn signal_wait_for_channel() {
+        use futures::executor::block_on;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let (tx, rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel();
+        let sig = Signal::fold_channel(0, rx, |a, n| a + n);
+        let handle = thread::spawn(move || {
+            for i in 1..=3 {
+                thread::sleep(Duration::from_millis(10));
+                tx.send(i).unwrap();
+            }
+            go_rx.recv().unwrap();
+            thread::sleep(Duration::from_millis(10));
+            tx.send(4).unwrap();
+        });
+
+        assert_eq!(
+            sig.wait_for_timeout(|a| *a >= 6, Duration::from_secs(5)),
+            Some(6)
+        );
+        go_tx.send(()).unwrap();
+        assert_eq!(block_on(sig.wait_for(|a| *a >= 10)), 10);
+        handle.join().unwrap();
+    }
 }
@@ -0,0 +1,493 @@
+//! Incremental reactive maps.
+//!
+//! A `SignalMap` is a map that changes over time, exposed as a stream of `MapDiff` events that
+//! describe each change. It's the keyed counterpart of `SignalVec`: operations applied on a
+//! `SignalMap` process only the changes, and single keys can be observed as signals.
+//!
+//! A `SignalMap` chain begins with a `MutableMap`, that applies the changes and sends them
+//! to the maps connected to it.
+//!
+//! # Example
+//! ```
+//! use frappe::signal_map::MutableMap;
+//!
+//! let users = MutableMap::new();
+//! let alice = users.signal_map().get(1);
+//! let names = users.signal_map().map_values(|name: &&str| name.to_uppercase()).entries();
+//!
+//! users.insert(2, "bob");
+//! users.insert(1, "alice");
+//! assert_eq!(alice.sample(), Some("alice"));
+//! assert_eq!(names.signal().sample(), [(1, "ALICE".to_string()), (2, "BOB".to_string())]);
+//!
+//! users.remove(&1);
+//! assert_eq!(alice.sample(), None);
+//! ```
+
+use crate::signal::Signal;
+use crate::signal_vec::{SignalVec, VecDiff};
+use crate::stream::{Sink, Stream};
+use crate::sync::Mutex;
+use crate::types::Storage;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A change made to a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapDiff<K, V> {
+    /// A new key was inserted.
+    Insert {
+        /// The new key.
+        key: K,
+        /// The new value.
+        value: V,
+    },
+    /// The value of an existing key was replaced.
+    Update {
+        /// The updated key.
+        key: K,
+        /// The new value.
+        value: V,
+    },
+    /// A key was removed.
+    Remove {
+        /// The removed key.
+        key: K,
+    },
+    /// All the keys were removed.
+    Clear,
+}
+
+impl<K: Ord, V> MapDiff<K, V> {
+    /// Applies this change to a map.
+    pub fn apply(self, map: &mut BTreeMap<K, V>) {
+        match self {
+            MapDiff::Insert { key, value } | MapDiff::Update { key, value } => {
+                map.insert(key, value);
+            }
+            MapDiff::Remove { key } => {
+                map.remove(&key);
+            }
+            MapDiff::Clear => map.clear(),
+        }
+    }
+}
+
+impl<K: Clone, V> MapDiff<K, V> {
+    /// Converts the value contained on this change.
+    pub fn map<R, F>(&self, f: F) -> MapDiff<K, R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        match self {
+            MapDiff::Insert { key, value } => MapDiff::Insert {
+                key: key.clone(),
+                value: f(value),
+            },
+            MapDiff::Update { key, value } => MapDiff::Update {
+                key: key.clone(),
+                value: f(value),
+            },
+            MapDiff::Remove { key } => MapDiff::Remove { key: key.clone() },
+            MapDiff::Clear => MapDiff::Clear,
+        }
+    }
+}
+
+/// A source of map changes that feeds the `SignalMap`s connected to it.
+///
+/// All the changes (and the creation of `SignalMap` operations) are serialized by a lock, so
+/// modifying this map from one of it's own change observers will deadlock.
+pub struct MutableMap<K, V> {
+    map: SignalMap<K, V>,
+    sink: Sink<MapDiff<K, V>>,
+}
+
+impl<K, V> MutableMap<K, V>
+where
+    K: Ord + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates an empty map.
+    #[inline]
+    pub fn new() -> Self {
+        MutableMap::from(BTreeMap::new())
+    }
+
+    /// Creates a read-only view of this map.
+    #[inline]
+    pub fn signal_map(&self) -> SignalMap<K, V> {
+        self.map.clone()
+    }
+
+    /// Inserts or updates a value, returning the previous one.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let _lock = self.map.lock.lock();
+        let old = self
+            .map
+            .storage
+            .modify(|map| map.insert(key.clone(), value.clone()));
+        self.sink.send(if old.is_some() {
+            MapDiff::Update { key, value }
+        } else {
+            MapDiff::Insert { key, value }
+        });
+        old
+    }
+
+    /// Removes a key, returning it's value.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let _lock = self.map.lock.lock();
+        let old = self.map.storage.modify(|map| map.remove(key));
+        if old.is_some() {
+            self.sink.send(MapDiff::Remove { key: key.clone() });
+        }
+        old
+    }
+
+    /// Removes all the keys.
+    pub fn clear(&self) {
+        let _lock = self.map.lock.lock();
+        self.map.storage.modify(BTreeMap::clear);
+        self.sink.send(MapDiff::Clear);
+    }
+}
+
+impl<K, V> From<BTreeMap<K, V>> for MutableMap<K, V>
+where
+    K: Ord + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a map with initial values.
+    fn from(map: BTreeMap<K, V>) -> Self {
+        let sink = Sink::new();
+        MutableMap {
+            map: SignalMap {
+                storage: Arc::new(Storage::new(map)),
+                diffs: sink.stream(),
+                lock: Default::default(),
+            },
+            sink,
+        }
+    }
+}
+
+impl<K, V> Default for MutableMap<K, V>
+where
+    K: Ord + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates an empty map.
+    #[inline]
+    fn default() -> Self {
+        MutableMap::new()
+    }
+}
+
+impl<K, V> Clone for MutableMap<K, V> {
+    /// Creates a copy of this map that references the same values.
+    fn clone(&self) -> Self {
+        MutableMap {
+            map: self.map.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for MutableMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MutableMap(Storage@{:p})", self.map.storage)
+    }
+}
+
+/// A map that changes over time, exposed as a stream of changes.
+pub struct SignalMap<K, V> {
+    storage: Arc<Storage<BTreeMap<K, V>>>,
+    diffs: Stream<MapDiff<K, V>>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl<K, V> SignalMap<K, V>
+where
+    K: Ord + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a map that is updated by a function of the changes of this map.
+    ///
+    /// The closure receives each change, the output storage and the output sender, and it must
+    /// send the resulting changes (if any). They will be applied to the output storage before
+    /// being sent.
+    fn derive<R, F>(&self, initial: BTreeMap<K, R>, f: F) -> SignalMap<K, R>
+    where
+        F: Fn(&MapDiff<K, V>, &Storage<BTreeMap<K, R>>, &mut dyn FnMut(MapDiff<K, R>))
+            + Send
+            + Sync
+            + 'static,
+        R: Clone + Send + Sync + 'static,
+    {
+        let storage = Arc::new(Storage::new(initial));
+        let st = storage.clone();
+        let diffs = self.diffs.map_n(move |diff, sender| {
+            f(&diff, &st, &mut |out| {
+                st.modify(|map| out.clone().apply(map));
+                sender.send(out);
+            })
+        });
+        SignalMap {
+            storage,
+            diffs,
+            lock: self.lock.clone(),
+        }
+    }
+
+    /// Creates a stream that receives the changes made to this map.
+    ///
+    /// Only the changes made after this call are received, use `SignalMap::signal` to read the
+    /// current values.
+    #[inline]
+    pub fn diffs(&self) -> Stream<MapDiff<K, V>> {
+        self.diffs.clone()
+    }
+
+    /// Creates a signal that reads the current values by cloning them.
+    pub fn signal(&self) -> Signal<BTreeMap<K, V>> {
+        Signal::from_storage(self.storage.clone(), self.diffs.clone())
+    }
+
+    /// Creates a signal that reads the current value of a single key.
+    pub fn get(&self, key: K) -> Signal<Option<V>> {
+        let storage = self.storage.clone();
+        let diffs = self.diffs.clone();
+        Signal::from_fn_watch(
+            move || {
+                let _keepalive = &diffs;
+                storage.with(|map| map.get(&key).cloned())
+            },
+            Some(self.storage.clone()),
+        )
+    }
+
+    /// Creates a signal that reads the current amount of keys on this map.
+    pub fn len(&self) -> Signal<usize> {
+        let storage = self.storage.clone();
+        let diffs = self.diffs.clone();
+        Signal::from_fn_watch(
+            move || {
+                let _keepalive = &diffs;
+                storage.with(BTreeMap::len)
+            },
+            Some(self.storage.clone()),
+        )
+    }
+
+    /// Maps the values of this map using the provided function.
+    ///
+    /// The function is only called for the values that are inserted or updated.
+    pub fn map_values<F, R>(&self, f: F) -> SignalMap<K, R>
+    where
+        F: Fn(&V) -> R + Send + Sync + 'static,
+        R: Clone + Send + Sync + 'static,
+    {
+        let _lock = self.lock.lock();
+        let initial = self.storage.with(|map| {
+            map.iter()
+                .map(|(key, value)| (key.clone(), f(value)))
+                .collect()
+        });
+        self.derive(initial, move |diff, _, send| send(diff.map(&f)))
+    }
+
+    /// Creates a map that only contains the entries where the predicate is `true`.
+    ///
+    /// The predicate is only called for the values that are inserted or updated.
+    pub fn filter<F>(&self, pred: F) -> SignalMap<K, V>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        let _lock = self.lock.lock();
+        let initial = self.storage.with(|map| {
+            map.iter()
+                .filter(|(key, value)| pred(key, value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        });
+        self.derive(initial, move |diff, filtered, send| match diff {
+            MapDiff::Insert { key, value } | MapDiff::Update { key, value } => {
+                let was = filtered.with(|map| map.contains_key(key));
+                let key = key.clone();
+                match (was, pred(&key, value)) {
+                    (true, true) => send(MapDiff::Update {
+                        key,
+                        value: value.clone(),
+                    }),
+                    (true, false) => send(MapDiff::Remove { key }),
+                    (false, true) => send(MapDiff::Insert {
+                        key,
+                        value: value.clone(),
+                    }),
+                    (false, false) => (),
+                }
+            }
+            MapDiff::Remove { key } => {
+                if filtered.with(|map| map.contains_key(key)) {
+                    send(MapDiff::Remove { key: key.clone() })
+                }
+            }
+            MapDiff::Clear => send(MapDiff::Clear),
+        })
+    }
+
+    /// Creates a vector with the entries of this map sorted by key.
+    pub fn entries(&self) -> SignalVec<(K, V)> {
+        let _lock = self.lock.lock();
+        let initial: Vec<_> = self.storage.with(|map| {
+            map.iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        });
+        let storage = Arc::new(Storage::new(initial));
+        let st = storage.clone();
+        let diffs = self.diffs.map_n(move |diff, sender| {
+            let find = |key: &K| st.with(|vec| vec.binary_search_by(|(k, _)| k.cmp(key)));
+            let out = match *diff {
+                MapDiff::Insert { ref key, ref value } | MapDiff::Update { ref key, ref value } => {
+                    let entry = (key.clone(), value.clone());
+                    match find(key) {
+                        Ok(index) => VecDiff::Update {
+                            index,
+                            value: entry,
+                        },
+                        Err(index) => VecDiff::Insert {
+                            index,
+                            value: entry,
+                        },
+                    }
+                }
+                MapDiff::Remove { ref key } => match find(key) {
+                    Ok(index) => VecDiff::Remove { index },
+                    Err(_) => return,
+                },
+                MapDiff::Clear => VecDiff::Clear,
+            };
+            st.modify(|vec| out.clone().apply(vec));
+            sender.send(out);
+        });
+        SignalVec::new(storage, diffs, self.lock.clone())
+    }
+}
+
+impl<K, V> Clone for SignalMap<K, V> {
+    /// Creates a copy of this map that references the same values.
+    fn clone(&self) -> Self {
+        SignalMap {
+            storage: self.storage.clone(),
+            diffs: self.diffs.clone(),
+            lock: self.lock.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for SignalMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SignalMap(Storage@{:p})", self.storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_map_mutable() {
+        let map = MutableMap::new();
+        let key1 = map.signal_map().get(1);
+        let len = map.signal_map().len();
+        let diffs = map.signal_map().diffs().collect::<Vec<_>>();
+
+        assert_eq!(map.insert(1, "a"), None);
+        assert_eq!(map.insert(2, "b"), None);
+        assert_eq!(key1.sample(), Some("a"));
+        assert_eq!(map.insert(1, "c"), Some("a"));
+        assert_eq!(key1.sample(), Some("c"));
+        assert_eq!(len.sample(), 2);
+        assert_eq!(map.remove(&1), Some("c"));
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(key1.sample(), None);
+        map.clear();
+        assert_eq!(len.sample(), 0);
+
+        assert_eq!(
+            diffs.sample(),
+            [
+                MapDiff::Insert { key: 1, value: "a" },
+                MapDiff::Insert { key: 2, value: "b" },
+                MapDiff::Update { key: 1, value: "c" },
+                MapDiff::Remove { key: 1 },
+                MapDiff::Clear,
+            ]
+        );
+    }
+
+    #[test]
+    fn signal_map_incremental() {
+        let map = MutableMap::from(
+            vec![(3, 30), (1, 10)]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        );
+        let source = map.signal_map();
+        let halved = source.map_values(|v| v / 2);
+        let big = source.filter(|_, v| *v >= 20);
+        let entries = source.entries();
+        let big_entries = big.entries();
+        let big_diffs = big.diffs().collect::<Vec<_>>();
+
+        let check = || {
+            let cur = source.signal().sample();
+            let expected_halved: BTreeMap<_, _> = cur.iter().map(|(k, v)| (*k, v / 2)).collect();
+            let expected_big: BTreeMap<_, _> = cur
+                .iter()
+                .filter(|(_, v)| **v >= 20)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(halved.signal().sample(), expected_halved);
+            assert_eq!(big.signal().sample(), expected_big);
+            assert_eq!(
+                entries.signal().sample(),
+                cur.into_iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                big_entries.signal().sample(),
+                expected_big.into_iter().collect::<Vec<_>>()
+            );
+        };
+
+        check();
+        map.insert(2, 20);
+        check();
+        map.insert(1, 40);
+        check();
+        map.insert(3, 5);
+        check();
+        map.remove(&2);
+        check();
+        map.clear();
+        check();
+        map.insert(0, 100);
+        check();
+
+        assert_eq!(
+            big_diffs.sample(),
+            [
+                MapDiff::Insert { key: 2, value: 20 },
+                MapDiff::Insert { key: 1, value: 40 },
+                MapDiff::Remove { key: 3 },
+                MapDiff::Remove { key: 2 },
+                MapDiff::Clear,
+                MapDiff::Insert { key: 0, value: 100 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,627 @@
+//! Incremental reactive vectors.
+//!
+//! A `SignalVec` is a vector that changes over time, like a `Signal<Vec<T>>`, but instead of
+//! exposing the whole value on every change it exposes a stream of `VecDiff` events that describe
+//! each change. Operations applied on a `SignalVec` process only those changes, so the closures
+//! are only called for the values that changed instead of recomputing the whole vector.
+//!
+//! This doesn't make the updates constant time: every `SignalVec` stores it's own copy of the
+//! values, and inserting into or removing from it moves the values after that position. Some
+//! operations also keep bookkeeping that is linear on the vector length, see their documentation.
+//!
+//! A `SignalVec` chain begins with a `MutableVec`, that applies the changes and sends them
+//! to the vectors connected to it.
+//!
+//! # Example
+//! ```
+//! use frappe::signal_vec::MutableVec;
+//!
+//! let vec: MutableVec<i32> = MutableVec::new();
+//! let doubled = vec.signal_vec().map(|x| x * 2);
+//! let sorted = doubled.sort_by(|a, b| a.cmp(b));
+//!
+//! vec.push(3);
+//! vec.push(1);
+//! vec.push(2);
+//! vec.set(0, 10);
+//! assert_eq!(doubled.signal().sample(), [20, 2, 4]);
+//! assert_eq!(sorted.signal().sample(), [2, 4, 20]);
+//! ```
+
+use crate::signal::Signal;
+use crate::stream::{Sink, Stream};
+use crate::sync::Mutex;
+use crate::types::Storage;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// A change made to a vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VecDiff<T> {
+    /// A value was inserted at `index`.
+    Insert {
+        /// Position of the new value.
+        index: usize,
+        /// The new value.
+        value: T,
+    },
+    /// The value at `index` was replaced.
+    Update {
+        /// Position of the updated value.
+        index: usize,
+        /// The new value.
+        value: T,
+    },
+    /// The value at `index` was removed.
+    Remove {
+        /// Position of the removed value.
+        index: usize,
+    },
+    /// The value at `old_index` was removed and then inserted at `new_index`.
+    Move {
+        /// Position of the value before the move.
+        old_index: usize,
+        /// Position of the value after the move.
+        new_index: usize,
+    },
+    /// All the values were removed.
+    Clear,
+}
+
+impl<T> VecDiff<T> {
+    /// Applies this change to a vector.
+    pub fn apply(self, vec: &mut Vec<T>) {
+        match self {
+            VecDiff::Insert { index, value } => vec.insert(index, value),
+            VecDiff::Update { index, value } => vec[index] = value,
+            VecDiff::Remove { index } => {
+                vec.remove(index);
+            }
+            VecDiff::Move {
+                old_index,
+                new_index,
+            } => {
+                let value = vec.remove(old_index);
+                vec.insert(new_index, value);
+            }
+            VecDiff::Clear => vec.clear(),
+        }
+    }
+
+    /// Converts the value contained on this change.
+    pub fn map<R, F>(&self, f: F) -> VecDiff<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        match *self {
+            VecDiff::Insert { index, ref value } => VecDiff::Insert {
+                index,
+                value: f(value),
+            },
+            VecDiff::Update { index, ref value } => VecDiff::Update {
+                index,
+                value: f(value),
+            },
+            VecDiff::Remove { index } => VecDiff::Remove { index },
+            VecDiff::Move {
+                old_index,
+                new_index,
+            } => VecDiff::Move {
+                old_index,
+                new_index,
+            },
+            VecDiff::Clear => VecDiff::Clear,
+        }
+    }
+}
+
+/// A source of vector changes that feeds the `SignalVec`s connected to it.
+///
+/// All the changes (and the creation of `SignalVec` operations) are serialized by a lock, so
+/// modifying this vector from one of it's own change observers will deadlock.
+pub struct MutableVec<T> {
+    vec: SignalVec<T>,
+    sink: Sink<VecDiff<T>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> MutableVec<T> {
+    /// Creates an empty vector.
+    #[inline]
+    pub fn new() -> Self {
+        MutableVec::from(Vec::new())
+    }
+
+    /// Creates a read-only view of this vector.
+    #[inline]
+    pub fn signal_vec(&self) -> SignalVec<T> {
+        self.vec.clone()
+    }
+
+    /// Applies a change and sends it to the connected vectors.
+    pub fn apply(&self, diff: VecDiff<T>) {
+        let _lock = self.vec.lock.lock();
+        self.vec.storage.modify(|vec| diff.clone().apply(vec));
+        self.sink.send(diff);
+    }
+
+    /// Appends a value at the end.
+    pub fn push(&self, value: T) {
+        let _lock = self.vec.lock.lock();
+        let index = self.vec.storage.modify(|vec| {
+            vec.push(value.clone());
+            vec.len() - 1
+        });
+        self.sink.send(VecDiff::Insert { index, value });
+    }
+
+    /// Inserts a value at `index`.
+    #[inline]
+    pub fn insert(&self, index: usize, value: T) {
+        self.apply(VecDiff::Insert { index, value })
+    }
+
+    /// Replaces the value at `index`.
+    #[inline]
+    pub fn set(&self, index: usize, value: T) {
+        self.apply(VecDiff::Update { index, value })
+    }
+
+    /// Removes and returns the value at `index`.
+    pub fn remove(&self, index: usize) -> T {
+        let _lock = self.vec.lock.lock();
+        let value = self.vec.storage.modify(|vec| vec.remove(index));
+        self.sink.send(VecDiff::Remove { index });
+        value
+    }
+
+    /// Moves the value at `old_index` to `new_index`.
+    #[inline]
+    pub fn move_item(&self, old_index: usize, new_index: usize) {
+        self.apply(VecDiff::Move {
+            old_index,
+            new_index,
+        })
+    }
+
+    /// Removes all the values.
+    #[inline]
+    pub fn clear(&self) {
+        self.apply(VecDiff::Clear)
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> From<Vec<T>> for MutableVec<T> {
+    /// Creates a vector with initial values.
+    fn from(vec: Vec<T>) -> Self {
+        let sink = Sink::new();
+        MutableVec {
+            vec: SignalVec::new(
+                Arc::new(Storage::new(vec)),
+                sink.stream(),
+                Default::default(),
+            ),
+            sink,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Default for MutableVec<T> {
+    /// Creates an empty vector.
+    #[inline]
+    fn default() -> Self {
+        MutableVec::new()
+    }
+}
+
+impl<T> Clone for MutableVec<T> {
+    /// Creates a copy of this vector that references the same values.
+    fn clone(&self) -> Self {
+        MutableVec {
+            vec: self.vec.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for MutableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MutableVec(Storage@{:p})", self.vec.storage)
+    }
+}
+
+/// A vector that changes over time, exposed as a stream of changes.
+pub struct SignalVec<T> {
+    storage: Arc<Storage<Vec<T>>>,
+    diffs: Stream<VecDiff<T>>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl<T> SignalVec<T> {
+    /// Creates a vector from it's components.
+    pub(crate) fn new(
+        storage: Arc<Storage<Vec<T>>>,
+        diffs: Stream<VecDiff<T>>,
+        lock: Arc<Mutex<()>>,
+    ) -> Self {
+        SignalVec {
+            storage,
+            diffs,
+            lock,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> SignalVec<T> {
+    /// Creates a vector that is updated by a function of the changes of this vector.
+    ///
+    /// The closure receives each change, the output storage and the output sender, and it must
+    /// send the resulting changes (if any). They will be applied to the output storage before
+    /// being sent.
+    fn derive<R, F>(&self, initial: Vec<R>, f: F) -> SignalVec<R>
+    where
+        F: Fn(&VecDiff<T>, &Storage<Vec<R>>, &mut dyn FnMut(VecDiff<R>)) + Send + Sync + 'static,
+        R: Clone + Send + Sync + 'static,
+    {
+        let storage = Arc::new(Storage::new(initial));
+        let st = storage.clone();
+        let diffs = self.diffs.map_n(move |diff, sender| {
+            f(&diff, &st, &mut |out| {
+                st.modify(|vec| out.clone().apply(vec));
+                sender.send(out);
+            })
+        });
+        SignalVec::new(storage, diffs, self.lock.clone())
+    }
+
+    /// Creates a stream that receives the changes made to this vector.
+    ///
+    /// Only the changes made after this call are received, use `SignalVec::signal` to read the
+    /// current values.
+    #[inline]
+    pub fn diffs(&self) -> Stream<VecDiff<T>> {
+        self.diffs.clone()
+    }
+
+    /// Creates a signal that reads the current values by cloning them.
+    pub fn signal(&self) -> Signal<Vec<T>> {
+        Signal::from_storage(self.storage.clone(), self.diffs.clone())
+    }
+
+    /// Creates a signal that reads the current length of this vector.
+    pub fn len(&self) -> Signal<usize> {
+        let storage = self.storage.clone();
+        let diffs = self.diffs.clone();
+        Signal::from_fn_watch(
+            move || {
+                let _keepalive = &diffs;
+                storage.with(Vec::len)
+            },
+            Some(self.storage.clone()),
+        )
+    }
+
+    /// Maps the values of this vector using the provided function.
+    ///
+    /// The function is only called for the values that are inserted or updated.
+    pub fn map<F, R>(&self, f: F) -> SignalVec<R>
+    where
+        F: Fn(&T) -> R + Send + Sync + 'static,
+        R: Clone + Send + Sync + 'static,
+    {
+        let _lock = self.lock.lock();
+        let initial = self.storage.with(|vec| vec.iter().map(&f).collect());
+        self.derive(initial, move |diff, _, send| send(diff.map(&f)))
+    }
+
+    /// Creates a vector that only contains the values where the predicate is `true`.
+    ///
+    /// The predicate is only called for the values that are inserted or updated. Translating the
+    /// position of a change counts the kept values before it, so it takes `O(n)` time.
+    pub fn filter<F>(&self, pred: F) -> SignalVec<T>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let _lock = self.lock.lock();
+        let (mask, initial) = self.storage.with(|vec| {
+            let mask: Vec<_> = vec.iter().map(&pred).collect();
+            let initial = vec
+                .iter()
+                .zip(&mask)
+                .filter(|&(_, keep)| *keep)
+                .map(|(val, _)| val.clone())
+                .collect();
+            (mask, initial)
+        });
+        // which of the source values are present on the output
+        let mask = Mutex::new(mask);
+        // position on the output of the source value at `index`
+        fn out_pos(mask: &[bool], index: usize) -> usize {
+            mask[..index].iter().filter(|keep| **keep).count()
+        }
+        self.derive(initial, move |diff, _, send| {
+            let mut mask = mask.lock();
+            match *diff {
+                VecDiff::Insert { index, ref value } => {
+                    let keep = pred(value);
+                    mask.insert(index, keep);
+                    if keep {
+                        send(VecDiff::Insert {
+                            index: out_pos(&mask, index),
+                            value: value.clone(),
+                        })
+                    }
+                }
+                VecDiff::Update { index, ref value } => {
+                    let was = mask[index];
+                    let keep = pred(value);
+                    mask[index] = keep;
+                    let index = out_pos(&mask, index);
+                    match (was, keep) {
+                        (true, true) => send(VecDiff::Update {
+                            index,
+                            value: value.clone(),
+                        }),
+                        (true, false) => send(VecDiff::Remove { index }),
+                        (false, true) => send(VecDiff::Insert {
+                            index,
+                            value: value.clone(),
+                        }),
+                        (false, false) => (),
+                    }
+                }
+                VecDiff::Remove { index } => {
+                    if mask.remove(index) {
+                        send(VecDiff::Remove {
+                            index: out_pos(&mask, index),
+                        })
+                    }
+                }
+                VecDiff::Move {
+                    old_index,
+                    new_index,
+                } => {
+                    let old_pos = out_pos(&mask, old_index);
+                    let keep = mask.remove(old_index);
+                    mask.insert(new_index, keep);
+                    let new_pos = out_pos(&mask, new_index);
+                    if keep && old_pos != new_pos {
+                        send(VecDiff::Move {
+                            old_index: old_pos,
+                            new_index: new_pos,
+                        })
+                    }
+                }
+                VecDiff::Clear => {
+                    mask.clear();
+                    send(VecDiff::Clear)
+                }
+            }
+        })
+    }
+
+    /// Creates a vector that contains the values of this vector sorted by a comparison function.
+    ///
+    /// The order of the values that compare equal is unspecified. Moving values on the source
+    /// vector doesn't change the output.
+    ///
+    /// The position of an inserted or updated value is found with a binary search, but inserting
+    /// or removing a value updates the stored output position of every other value, so the
+    /// changes take `O(n)` time.
+    pub fn sort_by<F>(&self, cmp: F) -> SignalVec<T>
+    where
+        F: Fn(&T, &T) -> Ordering + Send + Sync + 'static,
+    {
+        let _lock = self.lock.lock();
+        let (positions, initial) = self.storage.with(|vec| {
+            let mut order: Vec<_> = (0..vec.len()).collect();
+            order.sort_by(|&a, &b| cmp(&vec[a], &vec[b]));
+            let mut positions = vec![0; vec.len()];
+            for (pos, &index) in order.iter().enumerate() {
+                positions[index] = pos;
+            }
+            let initial = order.into_iter().map(|index| vec[index].clone()).collect();
+            (positions, initial)
+        });
+        // position on the output of each source value
+        let positions = Mutex::new(positions);
+        self.derive(initial, move |diff, sorted, send| {
+            let mut positions = positions.lock();
+            let find_pos = |value: &T| {
+                sorted.with(|vec| vec.partition_point(|x| cmp(x, value) != Ordering::Greater))
+            };
+            let shift = |positions: &mut Vec<usize>, from: usize, inserted: bool| {
+                for p in positions.iter_mut().filter(|p| **p >= from) {
+                    if inserted {
+                        *p += 1
+                    } else {
+                        *p -= 1
+                    }
+                }
+            };
+            match *diff {
+                VecDiff::Insert { index, ref value } => {
+                    let pos = find_pos(value);
+                    shift(&mut positions, pos, true);
+                    positions.insert(index, pos);
+                    send(VecDiff::Insert {
+                        index: pos,
+                        value: value.clone(),
+                    })
+                }
+                VecDiff::Update { index, ref value } => {
+                    let old_pos = positions[index];
+                    let in_place = sorted.with(|vec| {
+                        (old_pos == 0 || cmp(&vec[old_pos - 1], value) != Ordering::Greater)
+                            && (old_pos + 1 == vec.len()
+                                || cmp(&vec[old_pos + 1], value) != Ordering::Less)
+                    });
+                    if in_place {
+                        return send(VecDiff::Update {
+                            index: old_pos,
+                            value: value.clone(),
+                        });
+                    }
+                    send(VecDiff::Remove { index: old_pos });
+                    shift(&mut positions, old_pos + 1, false);
+                    let pos = find_pos(value);
+                    shift(&mut positions, pos, true);
+                    positions[index] = pos;
+                    send(VecDiff::Insert {
+                        index: pos,
+                        value: value.clone(),
+                    })
+                }
+                VecDiff::Remove { index } => {
+                    let pos = positions.remove(index);
+                    shift(&mut positions, pos + 1, false);
+                    send(VecDiff::Remove { index: pos })
+                }
+                VecDiff::Move {
+                    old_index,
+                    new_index,
+                } => {
+                    let pos = positions.remove(old_index);
+                    positions.insert(new_index, pos);
+                }
+                VecDiff::Clear => {
+                    positions.clear();
+                    send(VecDiff::Clear)
+                }
+            }
+        })
+    }
+}
+
+impl<T> Clone for SignalVec<T> {
+    /// Creates a copy of this vector that references the same values.
+    fn clone(&self) -> Self {
+        SignalVec {
+            storage: self.storage.clone(),
+            diffs: self.diffs.clone(),
+            lock: self.lock.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SignalVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SignalVec(Storage@{:p})", self.storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_vec_mutable() {
+        let vec = MutableVec::from(vec![1, 2]);
+        let sig = vec.signal_vec().signal();
+        let len = vec.signal_vec().len();
+        let diffs = vec.signal_vec().diffs().collect::<Vec<_>>();
+
+        vec.push(3);
+        vec.insert(0, 0);
+        vec.set(1, 10);
+        vec.move_item(0, 3);
+        assert_eq!(vec.remove(1), 2);
+        assert_eq!(sig.sample(), [10, 3, 0]);
+        assert_eq!(len.sample(), 3);
+
+        vec.clear();
+        assert_eq!(len.sample(), 0);
+        assert_eq!(
+            diffs.sample(),
+            [
+                VecDiff::Insert { index: 2, value: 3 },
+                VecDiff::Insert { index: 0, value: 0 },
+                VecDiff::Update {
+                    index: 1,
+                    value: 10
+                },
+                VecDiff::Move {
+                    old_index: 0,
+                    new_index: 3
+                },
+                VecDiff::Remove { index: 1 },
+                VecDiff::Clear,
+            ]
+        );
+    }
+
+    #[test]
+    fn signal_vec_filter_diffs() {
+        let vec = MutableVec::from(vec![1, 2, 3]);
+        let even = vec.signal_vec().filter(|x| x % 2 == 0);
+        let diffs = even.diffs().collect::<Vec<_>>();
+
+        vec.push(4); // inserted at 1
+        vec.push(5); // ignored
+        vec.set(0, 6); // inserted at 0
+        vec.set(1, 7); // removed at 1
+        vec.remove(2); // ignored
+        assert_eq!(even.signal().sample(), [6, 4]);
+        assert_eq!(
+            diffs.sample(),
+            [
+                VecDiff::Insert { index: 1, value: 4 },
+                VecDiff::Insert { index: 0, value: 6 },
+                VecDiff::Remove { index: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn signal_vec_incremental() {
+        let vec = MutableVec::from(vec![5, 3, 8]);
+        let source = vec.signal_vec();
+        let mapped = source.map(|x| x * 10);
+        let filtered = source.filter(|x| x % 2 == 1);
+        let sorted = source.sort_by(|a, b| a.cmp(b));
+        let sorted_desc = mapped.sort_by(|a, b| b.cmp(a));
+        let filtered_len = filtered.len();
+
+        let check = || {
+            let cur = source.signal().sample();
+            let expected_mapped: Vec<_> = cur.iter().map(|x| x * 10).collect();
+            let expected_filtered: Vec<_> = cur.iter().cloned().filter(|x| x % 2 == 1).collect();
+            let mut expected_sorted = cur.clone();
+            expected_sorted.sort();
+            let mut expected_desc = expected_mapped.clone();
+            expected_desc.sort_by(|a, b| b.cmp(a));
+            assert_eq!(mapped.signal().sample(), expected_mapped);
+            assert_eq!(filtered.signal().sample(), expected_filtered);
+            assert_eq!(filtered_len.sample(), expected_filtered.len());
+            assert_eq!(sorted.signal().sample(), expected_sorted);
+            assert_eq!(sorted_desc.signal().sample(), expected_desc);
+        };
+
+        check();
+        vec.push(1);
+        check();
+        vec.insert(2, 7);
+        check();
+        vec.set(0, 2);
+        check();
+        vec.set(3, 9);
+        check();
+        vec.set(1, 4);
+        check();
+        vec.move_item(0, 4);
+        check();
+        vec.move_item(3, 1);
+        check();
+        vec.remove(2);
+        check();
+        vec.push(4);
+        vec.set(0, 4);
+        check();
+        vec.clear();
+        check();
+        vec.push(11);
+        check();
+    }
+}
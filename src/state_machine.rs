@@ -0,0 +1,351 @@
+//! Typed state machines driven by streams.
+//!
+//! A `StateMachine` is a builder where the transitions are declared per (state, event) pair.
+//! Building it over a stream of events produces a `Machine`, that exposes the current state as a
+//! signal, the transitions as a stream of `(from, to)` pairs, and the events that didn't match any
+//! transition as a separate stream.
+//!
+//! # Example
+//! ```
+//! use frappe::Sink;
+//! use frappe::state_machine::StateMachine;
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Conn { Idle, Connecting, Online }
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Ev { Dial, Connected, Hangup }
+//!
+//! let events = Sink::new();
+//! let machine = StateMachine::new(Conn::Idle)
+//!     .transition(Conn::Idle, Ev::Dial, Conn::Connecting)
+//!     .transition(Conn::Connecting, Ev::Connected, Conn::Online)
+//!     .transition_with(|s, e| match (s, e) {
+//!         (Conn::Connecting, Ev::Hangup) | (Conn::Online, Ev::Hangup) => Some(Conn::Idle),
+//!         _ => None,
+//!     })
+//!     .build(&events.stream());
+//! let state = machine.state();
+//! let rejected = machine.rejected().collect::<Vec<_>>();
+//!
+//! events.feed(vec![Ev::Dial, Ev::Hangup, Ev::Connected, Ev::Dial]);
+//! assert_eq!(state.sample(), Conn::Connecting);
+//! assert_eq!(rejected.sample(), [Ev::Connected]);
+//! ```
+
+use crate::signal::Signal;
+use crate::stream::{Sink, Stream};
+use crate::types::{MaybeOwned, Storage};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+type TransitionFn<S, E> = Box<dyn Fn(&S, &E) -> Option<S> + Send + Sync>;
+type HookFn<S> = Box<dyn Fn(&S) + Send + Sync>;
+
+/// Builder for a state machine with states `S` and events `E`.
+pub struct StateMachine<S, E> {
+    initial: S,
+    transitions: Vec<TransitionFn<S, E>>,
+    on_enter: Vec<(S, HookFn<S>)>,
+    on_exit: Vec<(S, HookFn<S>)>,
+}
+
+impl<S, E> StateMachine<S, E>
+where
+    S: Clone + Send + Sync + 'static,
+    E: Clone + Send + Sync + 'static,
+{
+    /// Creates a state machine that starts on the `initial` state.
+    pub fn new(initial: S) -> Self {
+        StateMachine {
+            initial,
+            transitions: Vec::new(),
+            on_enter: Vec::new(),
+            on_exit: Vec::new(),
+        }
+    }
+
+    /// Declares a transition from the state `from` to `to` when the `event` is received.
+    ///
+    /// The transitions are tried in the order they're declared, and the first one that matches
+    /// is taken.
+    pub fn transition(self, from: S, event: E, to: S) -> Self
+    where
+        S: PartialEq,
+        E: PartialEq,
+    {
+        self.transition_with(move |s, e| {
+            if *s == from && *e == event {
+                Some(to.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Declares transitions using a function that returns the next state for a (state, event) pair.
+    ///
+    /// Returning `None` means the pair isn't handled by this function.
+    pub fn transition_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&S, &E) -> Option<S> + Send + Sync + 'static,
+    {
+        self.transitions.push(Box::new(f));
+        self
+    }
+
+    /// Registers a hook that runs after the machine enters the `state`.
+    ///
+    /// The hook receives the state that was left. Transitions from a state into itself run both
+    /// the exit and the entry hooks.
+    pub fn on_enter<F>(mut self, state: S, f: F) -> Self
+    where
+        F: Fn(&S) + Send + Sync + 'static,
+        S: PartialEq,
+    {
+        self.on_enter.push((state, Box::new(f)));
+        self
+    }
+
+    /// Registers a hook that runs after the machine leaves the `state`.
+    ///
+    /// The hook receives the state that was entered. It runs before the entry hooks.
+    pub fn on_exit<F>(mut self, state: S, f: F) -> Self
+    where
+        F: Fn(&S) + Send + Sync + 'static,
+        S: PartialEq,
+    {
+        self.on_exit.push((state, Box::new(f)));
+        self
+    }
+
+    /// Runs the state machine over a stream of events.
+    pub fn build(self, events: &Stream<E>) -> Machine<S, E>
+    where
+        S: PartialEq,
+    {
+        let storage = Arc::new(Storage::new(self.initial.clone()));
+        let runner = Runner {
+            storage: storage.clone(),
+            transitions: self.transitions,
+            on_enter: self.on_enter,
+            on_exit: self.on_exit,
+        };
+        // the transitions run on a single observer that feeds an internal sink, so the output
+        // streams can be derived and observed any number of times without stepping again
+        let steps = Sink::new();
+        let sender = steps.clone();
+        events.observe(move |ev| sender.send(runner.step(ev)));
+        let steps = steps.stream();
+        Machine {
+            state: Signal::from_storage(storage, events.clone()),
+            transitions: steps.filter_ok(),
+            rejected: steps.filter_err(),
+        }
+    }
+}
+
+impl<S, E> fmt::Debug for StateMachine<S, E>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMachine")
+            .field("initial", &self.initial)
+            .field("transitions", &self.transitions.len())
+            .finish()
+    }
+}
+
+/// Processes the events of a running state machine.
+struct Runner<S, E> {
+    storage: Arc<Storage<S>>,
+    transitions: Vec<TransitionFn<S, E>>,
+    on_enter: Vec<(S, HookFn<S>)>,
+    on_exit: Vec<(S, HookFn<S>)>,
+}
+
+impl<S: Clone + PartialEq, E: Clone> Runner<S, E> {
+    /// Applies an event, returning the transition taken or the rejected event.
+    fn step(&self, event: MaybeOwned<'_, E>) -> Result<(S, S), E> {
+        let mut taken = None;
+        self.storage.replace(
+            |old| match self.transitions.iter().find_map(|f| f(&old, &event)) {
+                Some(new) => {
+                    taken = Some((old, new.clone()));
+                    new
+                }
+                None => old,
+            },
+        );
+        // the hooks run after the storage is released, so they can sample the machine state
+        let (from, to) = taken.ok_or_else(|| event.into_owned())?;
+        for (state, hook) in &self.on_exit {
+            if *state == from {
+                hook(&to);
+            }
+        }
+        for (state, hook) in &self.on_enter {
+            if *state == to {
+                hook(&from);
+            }
+        }
+        Ok((from, to))
+    }
+}
+
+/// A running state machine.
+#[derive(Debug, Clone)]
+pub struct Machine<S, E> {
+    state: Signal<S>,
+    transitions: Stream<(S, S)>,
+    rejected: Stream<E>,
+}
+
+impl<S, E> Machine<S, E> {
+    /// Returns a signal with the current state.
+    #[inline]
+    pub fn state(&self) -> Signal<S> {
+        self.state.clone()
+    }
+
+    /// Returns a stream that receives a `(from, to)` pair for every transition taken.
+    #[inline]
+    pub fn transitions(&self) -> Stream<(S, S)> {
+        self.transitions.clone()
+    }
+
+    /// Returns a stream that receives the events that didn't match any transition.
+    #[inline]
+    pub fn rejected(&self) -> Stream<E> {
+        self.rejected.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::BehaviorSink;
+    use crate::sync::Mutex;
+    use alloc::string::String;
+    use alloc::vec;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Door {
+        Open,
+        Closed,
+        Locked,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Action {
+        Open,
+        Close,
+        Lock(u32),
+        Unlock(u32),
+    }
+
+    #[test]
+    fn state_machine_transitions() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (log1, log2, log3) = (log.clone(), log.clone(), log.clone());
+        let sink = Sink::new();
+        let machine = StateMachine::new(Door::Closed)
+            .transition(Door::Closed, Action::Open, Door::Open)
+            .transition(Door::Open, Action::Close, Door::Closed)
+            .transition_with(|s, a| match (s, a) {
+                (Door::Closed, Action::Lock(_)) => Some(Door::Locked),
+                (Door::Locked, Action::Unlock(1234)) => Some(Door::Closed),
+                _ => None,
+            })
+            .on_exit(Door::Locked, move |to| {
+                log1.lock().push(format!("unlocked into {:?}", to))
+            })
+            .on_enter(Door::Locked, move |from| {
+                log2.lock().push(format!("locked from {:?}", from))
+            })
+            .on_enter(Door::Closed, move |_| {
+                log3.lock().push(String::from("closed"))
+            })
+            .build(&sink.stream());
+        let state = machine.state();
+        let transitions = machine.transitions().collect::<Vec<_>>();
+        let rejected = machine.rejected().collect::<Vec<_>>();
+        drop(machine);
+
+        assert_eq!(state.sample(), Door::Closed);
+        sink.feed(vec![
+            Action::Open,
+            Action::Lock(1),
+            Action::Close,
+            Action::Lock(1),
+            Action::Unlock(42),
+            Action::Unlock(1234),
+        ]);
+
+        assert_eq!(state.sample(), Door::Closed);
+        assert_eq!(
+            transitions.sample(),
+            [
+                (Door::Closed, Door::Open),
+                (Door::Open, Door::Closed),
+                (Door::Closed, Door::Locked),
+                (Door::Locked, Door::Closed),
+            ]
+        );
+        assert_eq!(rejected.sample(), [Action::Lock(1), Action::Unlock(42)]);
+        assert_eq!(
+            *log.lock(),
+            [
+                "closed",
+                "locked from Closed",
+                "unlocked into Closed",
+                "closed"
+            ]
+        );
+    }
+
+    #[test]
+    fn state_machine_sample_on_transition() {
+        let sink = Sink::new();
+        let seen = Arc::new(Mutex::new(None));
+        let machine = StateMachine::new(0)
+            .transition_with(|s, e: &i32| Some(s + e))
+            .build(&sink.stream());
+        let state = machine.state();
+        let seen_ = seen.clone();
+        machine
+            .transitions()
+            .observe(move |_| *seen_.lock() = Some(state.sample()));
+
+        sink.send(5);
+        assert_eq!(*seen.lock(), Some(5));
+    }
+
+    #[test]
+    fn state_machine_replaying_source() {
+        let sink = BehaviorSink::new(1);
+        let entered = Arc::new(Mutex::new(0));
+        let entered_ = entered.clone();
+        let machine = StateMachine::new(0)
+            .transition_with(|s, e: &i32| if *e > 0 { Some(s + e) } else { None })
+            .on_enter(1, move |_| *entered_.lock() += 1)
+            .build(&sink.stream());
+        let state = machine.state();
+        assert_eq!(state.sample(), 1);
+
+        let transitions = machine.transitions().collect::<Vec<_>>();
+        let rejected = machine.rejected().collect::<Vec<_>>();
+        machine.transitions().observe(|_| ());
+        assert_eq!(state.sample(), 1);
+        assert_eq!(*entered.lock(), 1);
+
+        sink.send(2);
+        sink.send(0);
+        assert_eq!(state.sample(), 3);
+        assert_eq!(transitions.sample(), [(1, 3)]);
+        assert_eq!(rejected.sample(), [0]);
+        assert_eq!(*entered.lock(), 1);
+    }
+}
@@ -0,0 +1,384 @@
+//! Redux-style application state.
+//!
+//! A `Store` holds a state value that can only be changed by dispatching actions. Every action
+//! goes through the middleware chain, that can inspect, transform or drop it, and then the
+//! reducers compute the next state from it. The resulting state can be read as a signal, and
+//! selector signals derived from it are only recomputed when the state changes.
+//!
+//! # Example
+//! ```
+//! use frappe::store::Store;
+//!
+//! #[derive(Debug, Clone, Default)]
+//! struct Todos { items: Vec<String>, filter: String }
+//! #[derive(Debug, Clone)]
+//! enum Action { Add(String), Filter(String) }
+//!
+//! let store = Store::builder(Todos::default())
+//!     .reducer_on(|s: &mut Todos| &mut s.items, |mut items, a| {
+//!         if let Action::Add(item) = a { items.push(item.clone()) }
+//!         items
+//!     })
+//!     .reducer_on(|s: &mut Todos| &mut s.filter, |filter, a| match a {
+//!         Action::Filter(f) => f.clone(),
+//!         _ => filter,
+//!     })
+//!     // ignore empty items
+//!     .middleware(|_, a| match &a {
+//!         Action::Add(item) if item.is_empty() => None,
+//!         _ => Some(a),
+//!     })
+//!     .build();
+//! let visible = store.select(|s| {
+//!     s.items.iter().filter(|i| i.contains(&s.filter)).count()
+//! });
+//!
+//! store.dispatch(Action::Add("milk".into()));
+//! store.dispatch(Action::Add("".into()));
+//! store.dispatch(Action::Add("bread".into()));
+//! assert_eq!(store.sample().items.len(), 2);
+//! store.dispatch(Action::Filter("m".into()));
+//! assert_eq!(visible.sample(), 1);
+//! ```
+
+use crate::signal::Signal;
+use crate::stream::{Sink, Stream};
+use crate::sync::Mutex;
+use crate::types::Storage;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+type ReducerFn<S, A> = Box<dyn Fn(S, &A) -> S + Send + Sync>;
+type MiddlewareFn<S, A> = Box<dyn Fn(&S, A) -> Option<A> + Send + Sync>;
+
+/// Builder for a `Store`.
+pub struct StoreBuilder<S, A> {
+    initial: S,
+    reducers: Vec<ReducerFn<S, A>>,
+    middleware: Vec<MiddlewareFn<S, A>>,
+}
+
+impl<S, A> StoreBuilder<S, A>
+where
+    S: Clone + Send + Sync + 'static,
+    A: Clone + Send + Sync + 'static,
+{
+    /// Adds a reducer that computes the next state from the current one and an action.
+    ///
+    /// The reducers run in the order they're added, each one receiving the result of the
+    /// previous. They run while the state is locked, so they can't dispatch actions. They receive
+    /// a clone of the state, so if one panics the state is left unchanged.
+    pub fn reducer<F>(mut self, f: F) -> Self
+    where
+        F: Fn(S, &A) -> S + Send + Sync + 'static,
+    {
+        self.reducers.push(Box::new(f));
+        self
+    }
+
+    /// Adds a reducer that only handles a part of the state.
+    ///
+    /// The `get` function selects the part from the whole state, and `f` computes the next value
+    /// of that part.
+    pub fn reducer_on<U, G, F>(self, get: G, f: F) -> Self
+    where
+        U: Clone,
+        G: Fn(&mut S) -> &mut U + Send + Sync + 'static,
+        F: Fn(U, &A) -> U + Send + Sync + 'static,
+    {
+        self.reducer(move |mut state, action| {
+            let part = get(&mut state);
+            *part = f(part.clone(), action);
+            state
+        })
+    }
+
+    /// Adds a middleware that can inspect, transform or drop the dispatched actions.
+    ///
+    /// The middleware receives the current state and the action, and returns the action that
+    /// will be passed to the next middleware, or `None` to drop it. The middleware runs in the
+    /// order it's added, before the reducers. It runs under the same lock as the reducers, so the
+    /// state it receives is the one the action will be applied to, and like the reducers, it can't
+    /// dispatch actions.
+    pub fn middleware<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&S, A) -> Option<A> + Send + Sync + 'static,
+    {
+        self.middleware.push(Box::new(f));
+        self
+    }
+
+    /// Creates the store.
+    pub fn build(self) -> Store<S, A> {
+        let storage = Arc::new(Storage::new((0, self.initial)));
+        let st = storage.clone();
+        let reducers = self.reducers;
+        let middleware = self.middleware;
+        let actions = Sink::<A>::new();
+        let effects = actions.stream().filter_map(move |action| {
+            // the middleware and the reducers run under the same lock, so the middleware sees
+            // the state the action is applied to
+            st.modify(|(version, state)| {
+                let action = middleware
+                    .iter()
+                    .try_fold(action.into_owned(), |a, f| f(state, a))?;
+                // the reducers get a clone, so a panic doesn't leave the storage empty
+                *state = reducers.iter().fold(state.clone(), |s, f| f(s, &action));
+                *version += 1;
+                Some((action, state.clone()))
+            })
+        });
+        Store {
+            actions,
+            storage,
+            effects,
+        }
+    }
+}
+
+impl<S: fmt::Debug, A> fmt::Debug for StoreBuilder<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreBuilder")
+            .field("initial", &self.initial)
+            .field("reducers", &self.reducers.len())
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
+
+/// A state container that changes by dispatching actions.
+pub struct Store<S, A> {
+    actions: Sink<A>,
+    // the version is increased on every change, so selectors know when to recompute
+    storage: Arc<Storage<(usize, S)>>,
+    effects: Stream<(A, S)>,
+}
+
+impl<S, A> Store<S, A>
+where
+    S: Clone + Send + Sync + 'static,
+    A: Clone + Send + Sync + 'static,
+{
+    /// Creates a store builder with an initial state.
+    pub fn builder(initial: S) -> StoreBuilder<S, A> {
+        StoreBuilder {
+            initial,
+            reducers: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Dispatches an action.
+    #[inline]
+    pub fn dispatch(&self, action: A) {
+        self.actions.send(action)
+    }
+
+    /// Returns a sink that dispatches the actions sent to it.
+    #[inline]
+    pub fn dispatcher(&self) -> Sink<A> {
+        self.actions.clone()
+    }
+
+    /// Gets the current state.
+    pub fn sample(&self) -> S {
+        self.storage.with(|(_, s)| s.clone())
+    }
+
+    /// Creates a signal that reads the current state.
+    pub fn state(&self) -> Signal<S> {
+        Signal::from_storage(self.storage.clone(), self.effects.clone()).map(|(_, s)| s)
+    }
+
+    /// Creates a signal that reads a value derived from the state.
+    ///
+    /// The selector function is only called when the state has changed since the last sample.
+    pub fn select<R, F>(&self, f: F) -> Signal<R>
+    where
+        F: Fn(&S) -> R + Send + Sync + 'static,
+        R: Clone + Send + 'static,
+    {
+        let storage = self.storage.clone();
+        let cache = Mutex::new(None::<(usize, R)>);
+        let source = self.effects.clone();
+        let watch = self.storage.clone();
+        Signal::from_fn_watch(
+            move || {
+                let _keepalive = &source;
+                storage.with(|(version, state)| {
+                    let mut cache = cache.lock();
+                    match &*cache {
+                        Some((cached, val)) if cached == version => val.clone(),
+                        _ => {
+                            let val = f(state);
+                            *cache = Some((*version, val.clone()));
+                            val
+                        }
+                    }
+                })
+            },
+            Some(watch),
+        )
+    }
+
+    /// Returns a stream that receives every action applied, along with the resulting state.
+    ///
+    /// This is where side effects should be run. The observers can dispatch new actions.
+    #[inline]
+    pub fn effects(&self) -> Stream<(A, S)> {
+        self.effects.clone()
+    }
+}
+
+impl<S, A> Clone for Store<S, A> {
+    /// Creates a copy of this store that references the same state.
+    fn clone(&self) -> Self {
+        Store {
+            actions: self.actions.clone(),
+            storage: self.storage.clone(),
+            effects: self.effects.clone(),
+        }
+    }
+}
+
+impl<S, A> fmt::Debug for Store<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Store(Storage@{:p})", self.storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Action {
+        Inc(i32),
+        Reset,
+        Saved,
+    }
+
+    #[test]
+    fn store_reducers_middleware() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let log_ = log.clone();
+        let store = Store::builder((0, 0))
+            .reducer(|(n, saves), a| match a {
+                Action::Inc(x) => (n + x, saves),
+                Action::Reset => (0, saves),
+                Action::Saved => (n, saves + 1),
+            })
+            .middleware(move |s: &(i32, i32), a| {
+                log_.lock().push(s.0);
+                Some(a)
+            })
+            // negative increments are turned into a reset
+            .middleware(|_, a| match a {
+                Action::Inc(x) if x < 0 => Some(Action::Reset),
+                Action::Inc(0) => None,
+                a => Some(a),
+            })
+            .build();
+        let state = store.state();
+        let applied = store.effects().map(|e| e.0.clone()).collect::<Vec<_>>();
+
+        let dispatcher = store.dispatcher();
+        dispatcher.feed(vec![Action::Inc(2), Action::Inc(0), Action::Inc(3)]);
+        assert_eq!(state.sample(), (5, 0));
+        store.dispatch(Action::Inc(-1));
+        assert_eq!(store.sample(), (0, 0));
+
+        assert_eq!(
+            applied.sample(),
+            [Action::Inc(2), Action::Inc(3), Action::Reset]
+        );
+        assert_eq!(*log.lock(), [0, 2, 2, 5]);
+    }
+
+    #[test]
+    fn store_middleware_concurrent() {
+        use std::thread;
+
+        let store = Store::builder(0)
+            .reducer(|n, a: &i32| n + a)
+            // the middleware sees the state the action is applied to, so the cap is never passed
+            .middleware(|n, a| if *n < 100 { Some(a) } else { None })
+            .build();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let dispatcher = store.dispatcher();
+                thread::spawn(move || (0..100).for_each(|_| dispatcher.send(1)))
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(store.sample(), 100);
+    }
+
+    #[test]
+    fn store_reducer_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let store = Store::builder(vec![1])
+            .reducer(|mut v, a: &i32| {
+                assert!(*a >= 0, "negative value");
+                v.push(*a);
+                v
+            })
+            .build();
+
+        let res = catch_unwind(AssertUnwindSafe(|| store.dispatch(-1)));
+        assert!(res.is_err());
+        assert_eq!(store.sample(), [1]);
+        store.dispatch(2);
+        assert_eq!(store.sample(), [1, 2]);
+    }
+
+    #[test]
+    fn store_select_memoized() {
+        let store = Store::builder(vec![1, 2, 3])
+            .reducer(|mut v, a: &i32| {
+                v.push(*a);
+                v
+            })
+            .build();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_ = calls.clone();
+        let sum = store.select(move |v| {
+            calls_.fetch_add(1, Ordering::Relaxed);
+            v.iter().sum::<i32>()
+        });
+
+        assert_eq!(sum.sample(), 6);
+        assert_eq!(sum.sample(), 6);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        store.dispatch(4);
+        assert_eq!(sum.sample(), 10);
+        assert_eq!(sum.sample(), 10);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn store_effects_dispatch() {
+        let store = Store::builder((0, 0))
+            .reducer(|(n, saves), a| match a {
+                Action::Inc(x) => (n + x, saves),
+                Action::Reset => (0, saves),
+                Action::Saved => (n, saves + 1),
+            })
+            .build();
+        let dispatcher = store.dispatcher();
+        store.effects().observe(move |e| {
+            if let Action::Inc(_) = e.0 {
+                dispatcher.send(Action::Saved);
+            }
+        });
+
+        store.dispatch(Action::Inc(1));
+        store.dispatch(Action::Inc(1));
+        assert_eq!(store.sample(), (2, 2));
+    }
+}
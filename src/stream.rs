@@ -12,6 +12,11 @@
 //! so dropping intermediate temporary streams (like the ones created from chaining methods) won't
 //! break the chain.
 //!
+//! A stream ends when nothing can send values to it anymore, like when all the copies of it's sink
+//! are dropped, or when an operation like `Stream::take` finishes. The end is propagated down the
+//! chain, and the callbacks of an ended stream are dropped (so the channels created by
+//! `Stream::as_sync_channel` get disconnected). `Stream::last` waits for it.
+//!
 //! This implementation of Stream distributes the data as `MaybeOwned<T>` values to avoid
 //! unnecessary cloning, so the first observers will receive a `MaybeOwned::Borrowed` value, and the
 //! last one will receive a`MaybeOwned::Owned`. This also allows sending values as a reference with
@@ -34,31 +39,49 @@
 //! assert_eq!(signal.sample(), 20);
 //! ```
 
-use crate::futures::StreamFuture;
+use crate::futures::{Closed, Executor, LastFuture, StreamFuture};
 use crate::helpers::arc_and_weak;
+use crate::history::{History, Timeline};
 use crate::signal::Signal;
 use crate::sync::Mutex;
-use crate::types::{Callbacks, MaybeOwned, ObserveResult, Storage, SumType2};
-use std::any::Any;
-use std::collections::VecDeque;
-use std::ops::{Bound, RangeBounds};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use crate::types::{Callbacks, DropWatch, Feeder, MaybeOwned, ObserveResult, Storage, SumType2};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Waker;
 
 #[cfg(feature = "either")]
 use crate::types::Either;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(all(feature = "std", feature = "rayon"))]
+use std::panic::{self, AssertUnwindSafe};
 
 /// A source of events that feeds the streams connected to it.
 #[derive(Debug)]
 pub struct Sink<T> {
     cbs: Arc<Callbacks<T>>,
+    // the streams end when the last copy of the sink is dropped
+    _feeder: Feeder<T>,
 }
 
 impl<T> Sink<T> {
     /// Creates a new sink.
     pub fn new() -> Self {
+        let (cbs, feeder) = Callbacks::with_feeder();
         Sink {
-            cbs: Default::default(),
+            cbs,
+            _feeder: feeder,
         }
     }
 
@@ -97,7 +120,10 @@ impl<T> Sink<T> {
     /// This method sends a value to each of the Sink's connected streams simultaneously by spawning
     /// a thread for each one, then it waits for all threads to finish. The value is sent by
     /// reference, so no cloning is done.
-    #[cfg(feature = "crossbeam-utils")]
+    ///
+    /// With the `rayon` feature enabled, the streams are run on the current rayon thread pool
+    /// instead of spawning new threads on every call. Use `ThreadPool::install` to choose the pool.
+    #[cfg(all(feature = "std", any(feature = "crossbeam-utils", feature = "rayon")))]
     #[inline]
     pub fn send_parallel(&self, val: &T)
     where
@@ -120,14 +146,140 @@ impl<T> Clone for Sink<T> {
     fn clone(&self) -> Self {
         Sink {
             cbs: self.cbs.clone(),
+            _feeder: self._feeder.clone(),
+        }
+    }
+}
+
+/// A sink that replays the last values sent to it.
+///
+/// Every time a new callback is registered on one of it's streams (like when calling
+/// `Stream::observe` or `Stream::hold`), the last `N` values sent are delivered to it first.
+/// The values sent while a callback receives the replay are held back until it ends, so they
+/// always arrive after the replayed ones.
+///
+/// Only the callbacks registered directly on the sink's streams receive the replayed values: a
+/// stream operation like `Stream::map` receives them once when it's created, so it's closure never
+/// runs again for a value that was already sent.
+#[derive(Debug)]
+pub struct ReplaySink<T> {
+    cbs: Arc<Callbacks<T>>,
+    _feeder: Feeder<T>,
+}
+
+impl<T: Clone + Send + 'static> ReplaySink<T> {
+    /// Creates a new sink that replays the last `n` values sent.
+    pub fn new(n: usize) -> Self {
+        ReplaySink::with_callbacks(Callbacks::with_replay(n, None))
+    }
+}
+
+impl<T> ReplaySink<T> {
+    /// Creates a sink that sends it's values to a callback list.
+    fn with_callbacks(cbs: Callbacks<T>) -> Self {
+        let cbs = Arc::new(cbs);
+        ReplaySink {
+            _feeder: Feeder::new(&cbs),
+            cbs,
+        }
+    }
+
+    /// Creates a stream that receives the events sent to this sink.
+    pub fn stream(&self) -> Stream<T> {
+        Stream::new(self.cbs.clone(), Source::None)
+    }
+
+    /// Sends a value into the sink.
+    ///
+    /// A copy of the value is stored before it's sent.
+    #[inline]
+    pub fn send<'a>(&self, val: impl Into<MaybeOwned<'a, T>>)
+    where
+        T: Clone + 'a,
+    {
+        self.cbs.call_replay(val)
+    }
+
+    /// Sends multiple values into the sink.
+    #[inline]
+    pub fn feed<'a, I, U>(&self, iter: I)
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<MaybeOwned<'a, T>>,
+        T: Clone + 'a,
+    {
+        for val in iter {
+            self.send(val)
+        }
+    }
+}
+
+impl<T> Clone for ReplaySink<T> {
+    /// Creates a copy of this sink that references the same event source.
+    fn clone(&self) -> Self {
+        ReplaySink {
+            cbs: self.cbs.clone(),
+            _feeder: self._feeder.clone(),
         }
     }
 }
 
+/// A sink that delivers it's current value to new observers.
+///
+/// This is a `ReplaySink` that always holds a single value, so new observers can start from the
+/// current state without having to wait for the next update.
+#[derive(Debug)]
+pub struct BehaviorSink<T>(ReplaySink<T>);
+
+impl<T: Clone + Send + 'static> BehaviorSink<T> {
+    /// Creates a new sink with an initial value.
+    pub fn new(initial: T) -> Self {
+        BehaviorSink(ReplaySink::with_callbacks(Callbacks::with_replay(
+            1,
+            Some(initial),
+        )))
+    }
+}
+
+impl<T> BehaviorSink<T> {
+    /// Creates a stream that receives the events sent to this sink.
+    #[inline]
+    pub fn stream(&self) -> Stream<T> {
+        self.0.stream()
+    }
+
+    /// Sends a value into the sink, replacing the current one.
+    #[inline]
+    pub fn send<'a>(&self, val: impl Into<MaybeOwned<'a, T>>)
+    where
+        T: Clone + 'a,
+    {
+        self.0.send(val)
+    }
+
+    /// Sends multiple values into the sink.
+    #[inline]
+    pub fn feed<'a, I, U>(&self, iter: I)
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<MaybeOwned<'a, T>>,
+        T: Clone + 'a,
+    {
+        self.0.feed(iter)
+    }
+}
+
+impl<T> Clone for BehaviorSink<T> {
+    /// Creates a copy of this sink that references the same event source.
+    fn clone(&self) -> Self {
+        BehaviorSink(self.0.clone())
+    }
+}
+
 /// The source object of a Stream.
 ///
 /// This is used to create a strong reference to a parent stream.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 enum Source {
     /// No source.
     None,
@@ -145,6 +297,15 @@ impl Source {
     }
 }
 
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::None => write!(f, "None"),
+            Source::Erased(s) => write!(f, "Erased(Any@{:p})", s),
+        }
+    }
+}
+
 /// A stream of discrete events sent over time.
 #[derive(Debug)]
 pub struct Stream<T> {
@@ -201,6 +362,25 @@ impl<T> Stream<T> {
         });
     }
 
+    /// Observes the stream on behalf of an owner object that is referenced weakly.
+    ///
+    /// This is the same as `Stream::observe`, but the closure receives a reference to the owner
+    /// object on every call. Only a weak reference to the owner is stored, so the closure will be
+    /// dropped on the first event received after the owner is dropped.
+    pub fn observe_weak<O, F, R>(&self, owner: &Arc<O>, f: F)
+    where
+        O: Send + Sync + 'static,
+        F: Fn(&O, MaybeOwned<'_, T>) -> R + Send + Sync + 'static,
+        R: ObserveResult,
+    {
+        let weak = Arc::downgrade(owner);
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|owner| f(&owner, arg).is_callback_alive())
+                .unwrap_or(false)
+        });
+    }
+
     /// Chainable version of `Stream::observe`.
     #[inline]
     pub fn inspect<F, R>(self, f: F) -> Self
@@ -231,7 +411,7 @@ impl<T: 'static> Stream<T> {
     where
         F: Fn(&T) -> bool + Send + Sync + 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         self.cbs.push(move |arg| {
             with_weak!(weak, |cb| if pred(&arg) {
                 cb.call(arg)
@@ -248,7 +428,7 @@ impl<T: 'static> Stream<T> {
         F: Fn(MaybeOwned<'_, T>) -> Option<R> + Send + Sync + 'static,
         R: 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         self.cbs.push(move |arg| {
             with_weak!(weak, |cb| if let Some(val) = f(arg) {
                 cb.call(val)
@@ -259,7 +439,7 @@ impl<T: 'static> Stream<T> {
 
     /// Creates a new stream that fires with the events from both streams.
     pub fn merge(&self, other: &Stream<T>) -> Self {
-        let (new_cbs, weak1) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak1) = Callbacks::with_feeder();
         let weak2 = weak1.clone();
         self.cbs
             .push(move |arg| with_weak!(weak1, |cb| cb.call(arg)));
@@ -281,7 +461,7 @@ impl<T: 'static> Stream<T> {
         U: 'static,
         R: 'static,
     {
-        let (new_cbs, weak1) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak1) = Callbacks::with_feeder();
         let weak2 = weak1.clone();
         self.cbs
             .push(move |arg| with_weak!(weak1, |cb| cb.call(f1(arg))));
@@ -351,21 +531,91 @@ impl<T: 'static> Stream<T> {
         Signal::from_storage(storage, self.clone())
     }
 
+    /// Accumulates the values sent over this stream, keeping the previous states.
+    ///
+    /// This works like `Stream::fold`, but every value received saves the current state so it
+    /// can be restored later with `History::undo`. Up to `capacity` states are kept, the oldest
+    /// ones are discarded first. Receiving a new value clears the states that were undone.
+    pub fn fold_with_history<A, F>(&self, initial: A, f: F, capacity: usize) -> History<A>
+    where
+        F: Fn(A, MaybeOwned<'_, T>) -> A + Send + Sync + 'static,
+        A: Clone + Send + Sync + 'static,
+    {
+        let (timeline, weak) = arc_and_weak(Storage::new(Timeline::new(initial, capacity)));
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |tl| {
+                tl.replace(|old| old.edit(|acc| f(acc, arg)));
+            })
+        });
+        History::new(timeline, Arc::new(self.clone()))
+    }
+
     /// Maps each stream event to `0..N` output values.
     ///
     /// On every stream event received the closure must return its value by sending it through the
     /// provided Sender. Multiple values (or none) can be sent to the output stream this way.
     ///
     /// This primitive is useful to construct asynchronous operations, since you can store the
-    /// Sender and then use it when the data is ready.
+    /// Sender and then use it when the data is ready. The Sender doesn't keep the output stream
+    /// alive, so the background work can be stopped when it's dropped by checking
+    /// `Sender::is_closed` or waiting on a `Sender::cancel_token`.
     pub fn map_n<F, R>(&self, f: F) -> Stream<R>
     where
         F: Fn(MaybeOwned<'_, T>, Sender<R>) + Send + Sync + 'static,
         R: 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         self.cbs
-            .push(move |arg| with_weak!(weak, |cb| f(arg, Sender::new(cb))));
+            .push(move |arg| with_weak!(weak, |cb| f(arg, Sender::new(&cb))));
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Maps each stream event to a future, and sends it's result to the output stream.
+    ///
+    /// The futures are spawned on `executor`, with at most `concurrency` of them running at the
+    /// same time (zero means no limit). The rest wait in a queue until a running one completes.
+    /// If `ordered` is `true` the results are sent in the same order as the input values,
+    /// otherwise they're sent as soon as they're ready. The results are sent from the thread
+    /// that completes the future, or in ordered mode, from the one already sending results.
+    ///
+    /// If a future panics or is dropped by the executor before completing, its slot is released
+    /// and its value is skipped.
+    pub fn map_async<E, F, Fut>(
+        &self,
+        executor: E,
+        concurrency: usize,
+        ordered: bool,
+        f: F,
+    ) -> Stream<Fut::Output>
+    where
+        E: Executor + 'static,
+        F: Fn(MaybeOwned<'_, T>) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let tasks = Arc::new(AsyncTasks {
+            executor,
+            limit: concurrency,
+            ordered,
+            output: weak,
+            state: Mutex::new(AsyncTasksState {
+                next_id: 0,
+                next_out: 0,
+                running: 0,
+                queued: VecDeque::new(),
+                done: BTreeMap::new(),
+                ready: VecDeque::new(),
+                sending: false,
+            }),
+        });
+        self.cbs.push(move |arg| {
+            if tasks.output.strong_count() == 0 {
+                return false;
+            }
+            tasks.push(f(arg));
+            true
+        });
         Stream::new(new_cbs, Source::stream(self))
     }
 
@@ -378,7 +628,7 @@ impl<T: 'static> Stream<T> {
         F: Fn(A, MaybeOwned<'_, T>) -> A + Send + Sync + 'static,
         A: Clone + Send + Sync + 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         let storage = Storage::new(initial);
         self.cbs.push(move |arg| {
             with_weak!(weak, |cb| {
@@ -400,13 +650,13 @@ impl<T: 'static> Stream<T> {
         A: Send + Sync + 'static,
         R: 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         let storage = Storage::new(initial);
         self.cbs.push(move |arg| {
             with_weak!(weak, |cb| storage.replace(|old| f(
                 old,
                 arg,
-                Sender::new(cb)
+                Sender::new(&cb)
             )))
         });
         Stream::new(new_cbs, Source::stream(self))
@@ -427,7 +677,7 @@ impl<T: 'static> Stream<T> {
 
     /// Returns a stream that contains only the Nth value from the input stream.
     pub fn element_at(&self, index: usize) -> Self {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         let pos = AtomicUsize::new(0);
         self.cbs.push(move |arg| {
             weak.upgrade()
@@ -448,7 +698,7 @@ impl<T: 'static> Stream<T> {
     where
         B: RangeBounds<usize> + Send + Sync + 'static,
     {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         let pos = AtomicUsize::new(0);
         self.cbs.push(move |arg| {
             weak.upgrade()
@@ -473,6 +723,220 @@ impl<T: 'static> Stream<T> {
         });
         Stream::new(new_cbs, Source::stream(self))
     }
+
+    /// Returns a stream that contains only the first `n` values from the input stream.
+    pub fn take(&self, n: usize) -> Self {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let pos = AtomicUsize::new(0);
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|cb| {
+                    let cur_pos = pos.fetch_add(1, Ordering::Relaxed);
+                    if cur_pos < n {
+                        cb.call(arg);
+                    }
+                    cur_pos + 1 < n // drop the callback after the last value
+                })
+                .unwrap_or(false)
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that skips the first `n` values from the input stream.
+    #[inline]
+    pub fn skip(&self, n: usize) -> Self {
+        self.elements_between(n..)
+    }
+
+    /// Returns a stream that contains every `step`th value, starting with the first one.
+    ///
+    /// # Panics
+    /// Panics if `step` is zero.
+    pub fn step_by(&self, step: usize) -> Self {
+        assert!(step != 0, "step_by: step must be non-zero");
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let pos = AtomicUsize::new(0);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                // `usize::is_multiple_of` is too recent to use here
+                #[allow(clippy::manual_is_multiple_of)]
+                let send = pos.fetch_add(1, Ordering::Relaxed) % step == 0;
+                if send {
+                    cb.call(arg)
+                }
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that contains the values until the predicate returns `false`.
+    pub fn take_while<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        self.cbs.push(move |arg| {
+            weak.upgrade()
+                .map(|cb| {
+                    let keep = pred(&arg);
+                    if keep {
+                        cb.call(arg);
+                    }
+                    keep // drop the callback on the first rejected value
+                })
+                .unwrap_or(false)
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that skips the values until the predicate returns `false`.
+    pub fn skip_while<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let skipping = AtomicBool::new(true);
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                if skipping.load(Ordering::Relaxed) {
+                    if pred(&arg) {
+                        return;
+                    }
+                    skipping.store(false, Ordering::Relaxed);
+                }
+                cb.call(arg)
+            })
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Returns a stream that contains the values until `other` fires.
+    pub fn take_until<U: 'static>(&self, other: &Stream<U>) -> Self {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        // the feeder is dropped when `other` fires, so the output stream ends right away
+        let feeder = Arc::new(Mutex::new(Some(weak)));
+        let feeder_ = feeder.clone();
+        self.cbs.push(move |arg| {
+            let cb = feeder.lock().as_ref().and_then(Feeder::upgrade);
+            cb.map(|cb| cb.call(arg)).is_some()
+        });
+        other.cbs.push(move |_| {
+            let weak = feeder_.lock().take();
+            drop(weak);
+            false
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Returns a stream that skips the values until `other` fires.
+    pub fn skip_until<U: 'static>(&self, other: &Stream<U>) -> Self {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let started = Arc::new(AtomicBool::new(false));
+        let started_ = started.clone();
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| if started.load(Ordering::Relaxed) {
+                cb.call(arg)
+            })
+        });
+        other.cbs.push(move |_| {
+            started_.store(true, Ordering::Relaxed);
+            false
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Creates a stream that only lets values through while the signal is `true`.
+    ///
+    /// The signal is sampled on every value received, and the values sent while it's `false` are
+    /// discarded.
+    pub fn gate(&self, open: &Signal<bool>) -> Self {
+        let open = open.clone();
+        self.filter(move |_| open.sample())
+    }
+
+    /// Splits this stream into sub-streams grouped by key.
+    ///
+    /// Every time a value with a new key is received, a `(key, stream)` pair is sent to the
+    /// output stream, and then the value is sent to the new group stream. Later values with the
+    /// same key are sent to that group.
+    ///
+    /// A group expires when all of it's streams are dropped, so the next value with that key will
+    /// create a new group.
+    #[cfg(feature = "std")]
+    pub fn group_by<K, F>(&self, key_fn: F) -> Stream<(K, Stream<T>)>
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+        K: Hash + Eq + Clone + Send + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let groups = Mutex::new(HashMap::<K, Feeder<T>>::new());
+        // the output stream owns the parent reference, the groups get it from there
+        let parent: Arc<dyn Any + Send + Sync> = Arc::new(self.clone());
+        let parent_weak = Arc::downgrade(&parent);
+        self.cbs.push(move |arg| {
+            let key = key_fn(&arg);
+            let mut groups_ = groups.lock();
+            if let Some(group) = groups_.get(&key).and_then(Feeder::upgrade) {
+                drop(groups_);
+                group.call(arg);
+                return true;
+            }
+            if let (Some(cb), Some(parent)) = (weak.upgrade(), parent_weak.upgrade()) {
+                // remove the expired groups before adding a new one
+                groups_.retain(|_, group| group.strong_count() > 0);
+                let (group, group_weak) = Callbacks::with_feeder();
+                groups_.insert(key.clone(), group_weak);
+                drop(groups_);
+                cb.call((key, Stream::new(group.clone(), Source::Erased(parent))));
+                group.call(arg);
+                true
+            } else {
+                // drop callback if the output stream and all the groups are dropped
+                groups_.retain(|_, group| group.strong_count() > 0);
+                !groups_.is_empty()
+            }
+        });
+        Stream::new(new_cbs, Source::Erased(parent))
+    }
+
+    /// Splits this stream in two using a predicate.
+    ///
+    /// The values that satisfy the predicate are sent to the first stream, and the rest to the
+    /// second one. Each value is sent to only one stream, so no cloning is done.
+    pub fn partition<F>(&self, pred: F) -> (Stream<T>, Stream<T>)
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let mut streams = self.route(2, move |val| if pred(val) { 0 } else { 1 });
+        let stream_2 = streams.pop().unwrap();
+        let stream_1 = streams.pop().unwrap();
+        (stream_1, stream_2)
+    }
+
+    /// Splits this stream into `n` streams.
+    ///
+    /// The function returns the index of the stream that receives each value. Each value is sent
+    /// to only one stream, so no cloning is done. Values with an index out of range are discarded.
+    pub fn route<F>(&self, n: usize, f: F) -> Vec<Stream<T>>
+    where
+        F: Fn(&T) -> usize + Send + Sync + 'static,
+    {
+        let (cbs, weaks): (Vec<_>, Vec<_>) = (0..n).map(|_| Callbacks::with_feeder()).unzip();
+        self.cbs.push(move |arg| {
+            let index = f(&arg);
+            if let Some(cb) = weaks.get(index).and_then(Feeder::upgrade) {
+                cb.call(arg);
+                true
+            } else {
+                // drop callback if all output streams dropped
+                weaks.iter().any(|weak| weak.strong_count() > 0)
+            }
+        });
+        let source = Source::stream(self);
+        cbs.into_iter()
+            .map(|cbs| Stream::new(cbs, source.clone()))
+            .collect()
+    }
 }
 
 impl<T: Clone + Send + 'static> Stream<T> {
@@ -520,7 +984,7 @@ impl<T: Clone + Send + 'static> Stream<T> {
         U: Clone + Send + 'static,
         R: 'static,
     {
-        let (new_cbs, weak1) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak1) = Callbacks::with_feeder();
         let weak2 = weak1.clone();
 
         let left = Arc::new(Mutex::new(VecDeque::new()));
@@ -568,7 +1032,7 @@ impl<T: Clone + Send + 'static> Stream<T> {
         U: Clone + Send + 'static,
         R: 'static,
     {
-        let (new_cbs, weak1) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak1) = Callbacks::with_feeder();
         let weak2 = weak1.clone();
 
         let left = Arc::new(Mutex::new(None));
@@ -600,12 +1064,184 @@ impl<T: Clone + Send + 'static> Stream<T> {
         Stream::new(new_cbs, Source::stream2(self, other))
     }
 
+    /// Creates a stream that holds back the values while the signal is `false`.
+    ///
+    /// The values received while the signal is `false` are buffered, and then sent in order once
+    /// it becomes `true`. The signal is checked by sampling it on every value received, and also
+    /// when it's storage changes if it has one (like the signals created by `Stream::hold` or
+    /// `Var::signal`). Signals without storage only flush the buffer on the next value received.
+    pub fn pausable_buffered(&self, open: &Signal<bool>) -> Self {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let paused = Arc::new(Paused {
+            open: open.clone(),
+            state: Mutex::new(PausedState {
+                buffer: VecDeque::new(),
+                sending: false,
+            }),
+            busy: AtomicBool::new(false),
+            output: weak,
+        });
+        self.cbs.push(move |arg| {
+            let cb = match paused.output.upgrade() {
+                Some(cb) => cb,
+                None => return false,
+            };
+            if !paused.busy.load(Ordering::SeqCst) && paused.open.sample() {
+                cb.call(arg);
+            } else {
+                // keep the order by sending after the buffered values
+                let mut state = paused.state.lock();
+                state.buffer.push_back(arg.into_owned());
+                paused.busy.store(true, Ordering::SeqCst);
+                drop(state);
+                paused.flush();
+            }
+            true
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
+    /// Pairs the values of this stream with the last value seen on `other`.
+    ///
+    /// Unlike `Stream::combine`, this only fires when `self` receives a value. The values received
+    /// before `other` sends it's first value are discarded.
+    pub fn with_latest_from<U>(&self, other: &Stream<U>) -> Stream<(T, U)>
+    where
+        U: Clone + Send + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let (latest, latest_weak) = arc_and_weak(Mutex::new(None));
+        other.cbs.push(move |arg| {
+            with_weak!(latest_weak, |latest| {
+                *latest.lock() = Some(arg.into_owned());
+            })
+        });
+        self.cbs.push(move |arg| {
+            with_weak!(weak, |cb| {
+                let val = latest.lock().clone();
+                if let Some(val) = val {
+                    cb.call((arg.into_owned(), val));
+                }
+            })
+        });
+        Stream::new(new_cbs, Source::stream2(self, other))
+    }
+
+    /// Maps this stream using the rayon thread pool.
+    ///
+    /// Every value is mapped as a separate task on the pool, so multiple values can be processed
+    /// concurrently. The results are sent to the output stream in the same order as the input
+    /// values, from the pool thread that completes the next pending result.
+    ///
+    /// The tasks are spawned on the current rayon thread pool, or the global one if this is called
+    /// outside of a pool.
+    ///
+    /// If the closure panics, the panic is caught so it doesn't abort the pool, and that value is
+    /// skipped. The results after it are still sent. Panics from the output stream's callbacks are
+    /// caught the same way.
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    pub fn map_parallel<F, R>(&self, f: F) -> Stream<R>
+    where
+        F: Fn(T) -> R + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        let (new_cbs, weak) = Callbacks::with_feeder();
+        let f = Arc::new(f);
+        let next_id = AtomicUsize::new(0);
+        let parallel = Arc::new(Parallel {
+            state: Mutex::new(ParallelState {
+                next_out: 0,
+                done: BTreeMap::new(),
+                ready: VecDeque::new(),
+                sending: false,
+            }),
+            output: weak,
+        });
+        self.cbs.push(move |arg| {
+            if parallel.output.strong_count() == 0 {
+                return false;
+            }
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            let (val, f, parallel) = (arg.into_owned(), f.clone(), parallel.clone());
+            rayon::spawn(move || {
+                // a panic is recorded as a skip, so it doesn't hold back the next results
+                let res = panic::catch_unwind(AssertUnwindSafe(|| f(val))).ok();
+                parallel.finish(id, res);
+            });
+            true
+        });
+        Stream::new(new_cbs, Source::stream(self))
+    }
+
     /// Creates a future that returns the next value sent to this stream.
     #[inline]
     pub fn next(&self) -> StreamFuture<T> {
         StreamFuture::new(self.clone())
     }
-}
+
+    /// Creates a future that returns the next value where the predicate is `true`.
+    #[inline]
+    pub fn first_where<F>(&self, pred: F) -> StreamFuture<T>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.filter(pred).next()
+    }
+
+    /// Creates a future that returns the next value where the predicate is `true`.
+    ///
+    /// This is the same as `Stream::first_where`, named after `Iterator::find`.
+    #[inline]
+    pub fn find<F>(&self, pred: F) -> StreamFuture<T>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        self.first_where(pred)
+    }
+
+    /// Creates a future that returns the first `Some` value returned by the closure.
+    ///
+    /// This is the `Stream::filter_map` counterpart of `Stream::find`.
+    #[inline]
+    pub fn find_map<F, R>(&self, f: F) -> StreamFuture<R>
+    where
+        F: Fn(MaybeOwned<'_, T>) -> Option<R> + Send + Sync + 'static,
+        R: Clone + Send + 'static,
+    {
+        self.filter_map(f).next()
+    }
+
+    /// Creates a future that returns the last value sent to this stream after it ends.
+    ///
+    /// A stream ends when nothing can send values to it anymore: all the copies of it's sink are
+    /// dropped, or an operation like `Stream::take` or `Stream::take_until` finishes. The future
+    /// returns `None` if the stream ended without receiving any value.
+    #[inline]
+    pub fn last(&self) -> LastFuture<T> {
+        LastFuture::new(self.clone())
+    }
+
+    /// Creates a future that collects the next `n` values sent to this stream into a `Vec`.
+    pub fn collect_n(&self, n: usize) -> StreamFuture<Vec<T>>
+    where
+        T: Sync,
+    {
+        if n == 0 {
+            return StreamFuture::ready(Stream::never(), Vec::new());
+        }
+        self.take(n)
+            .scan_n(Vec::with_capacity(n), move |mut acc, val, sender| {
+                acc.push(val.into_owned());
+                if acc.len() == n {
+                    sender.send(acc);
+                    Vec::new()
+                } else {
+                    acc
+                }
+            })
+            .next()
+    }
+}
 
 impl<T: Clone + 'static> Stream<Option<T>> {
     /// Filters a stream of `Option`, returning only the unwrapped `Some` values.
@@ -658,8 +1294,8 @@ where
 
     /// Splits a two element sum type stream into two streams with the unwrapped values.
     pub fn split(&self) -> (Stream<T::Type1>, Stream<T::Type2>) {
-        let (cbs_1, weak_1) = arc_and_weak(Callbacks::new());
-        let (cbs_2, weak_2) = arc_and_weak(Callbacks::new());
+        let (cbs_1, weak_1) = Callbacks::with_feeder();
+        let (cbs_2, weak_2) = Callbacks::with_feeder();
         self.cbs.push(move |result| {
             if result.is_type1() {
                 if let Some(cb) = weak_1.upgrade() {
@@ -690,7 +1326,7 @@ where
 impl<T: 'static> Stream<Stream<T>> {
     /// Listens to the events from the last stream sent to a nested stream.
     pub fn switch(&self) -> Stream<T> {
-        let (new_cbs, weak) = arc_and_weak(Callbacks::new());
+        let (new_cbs, weak) = Callbacks::with_feeder();
         let id = Arc::new(AtomicUsize::new(0)); // id of each stream sent
         self.cbs.push(move |stream| {
             if weak.upgrade().is_none() {
@@ -731,35 +1367,420 @@ impl<T> Default for Stream<T> {
     }
 }
 
+/// The state of a `Stream::pausable_buffered` operation.
+struct Paused<T> {
+    open: Signal<bool>,
+    state: Mutex<PausedState<T>>,
+    // set while there are buffered values or they're being sent, so new values can't skip them
+    busy: AtomicBool,
+    output: Feeder<T>,
+}
+
+struct PausedState<T> {
+    buffer: VecDeque<T>,
+    // set while a thread sends the buffered values, so the others leave theirs to it
+    sending: bool,
+}
+
+/// Clears the sending flag if a callback panics while sending the buffered values.
+struct PausedSending<'a, T>(&'a Paused<T>);
+
+impl<T> Drop for PausedSending<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock();
+        state.sending = false;
+        self.0
+            .busy
+            .store(!state.buffer.is_empty(), Ordering::SeqCst);
+    }
+}
+
+impl<T: Send + 'static> Paused<T> {
+    /// Sends the buffered values while the signal is `true`, otherwise waits for it to change.
+    ///
+    /// The state isn't locked while calling back, so the callbacks can send new values.
+    fn flush(self: &Arc<Self>) {
+        let mut state = self.state.lock();
+        if state.sending {
+            return;
+        }
+        let mut batch = match self.take_batch(&mut state) {
+            Some(batch) => batch,
+            None => return,
+        };
+        state.sending = true;
+        drop(state);
+        let guard = PausedSending(self);
+        loop {
+            match self.output.upgrade() {
+                Some(cb) => batch.into_iter().for_each(|val| cb.call(val)),
+                None => drop(batch),
+            }
+            let mut state = self.state.lock();
+            batch = match self.take_batch(&mut state) {
+                Some(batch) => batch,
+                None => {
+                    state.sending = false;
+                    self.busy.store(!state.buffer.is_empty(), Ordering::SeqCst);
+                    break;
+                }
+            };
+        }
+        // another thread can be sending by now, so the flag must not be cleared again
+        mem::forget(guard);
+    }
+
+    /// Takes the buffered values if the signal is `true`.
+    fn take_batch(self: &Arc<Self>, state: &mut PausedState<T>) -> Option<VecDeque<T>> {
+        if state.buffer.is_empty() {
+            return None;
+        }
+        // watching before sampling, so a change right after the sample isn't missed
+        self.open.watch(&Waker::from(self.clone()));
+        if self.open.sample() {
+            Some(mem::take(&mut state.buffer))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Send + 'static> Wake for Paused<T> {
+    fn wake(self: Arc<Self>) {
+        self.flush()
+    }
+
+    /// Flushes the buffer when the signal changes.
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.flush()
+    }
+}
+
+/// The futures spawned by `Stream::map_async`.
+struct AsyncTasks<Fut: Future, E> {
+    executor: E,
+    limit: usize,
+    ordered: bool,
+    output: Feeder<Fut::Output>,
+    state: Mutex<AsyncTasksState<Fut>>,
+}
+
+struct AsyncTasksState<Fut: Future> {
+    next_id: usize,
+    next_out: usize,
+    running: usize,
+    queued: VecDeque<(usize, Fut)>,
+    // the results waiting for the previous ones, `None` when the future didn't complete
+    done: BTreeMap<usize, Option<Fut::Output>>,
+    // the ordered results waiting to be sent
+    ready: VecDeque<Fut::Output>,
+    // set while a thread sends the ordered results, so the others leave theirs to it
+    sending: bool,
+}
+
+/// Finishes a `Stream::map_async` task when dropped.
+///
+/// This also runs when the future panics or is dropped before completing, so it's slot is
+/// released and the ordered results after it aren't held back.
+struct AsyncTask<Fut, E>
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+    E: Executor + 'static,
+{
+    tasks: Arc<AsyncTasks<Fut, E>>,
+    id: usize,
+    res: Option<Fut::Output>,
+}
+
+impl<Fut, E> Drop for AsyncTask<Fut, E>
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+    E: Executor + 'static,
+{
+    fn drop(&mut self) {
+        self.tasks.finish(self.id, self.res.take());
+    }
+}
+
+/// Clears the sending flag if a callback panics while sending the ordered results.
+struct SendingGuard<'a, Fut: Future>(&'a Mutex<AsyncTasksState<Fut>>);
+
+impl<Fut: Future> Drop for SendingGuard<'_, Fut> {
+    fn drop(&mut self) {
+        self.0.lock().sending = false;
+    }
+}
+
+impl<Fut, E> AsyncTasks<Fut, E>
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+    E: Executor + 'static,
+{
+    /// Queues a future and starts it if there is a free slot.
+    fn push(self: &Arc<Self>, future: Fut) {
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queued.push_back((id, future));
+        drop(state);
+        self.start();
+    }
+
+    /// Spawns the queued futures until the concurrency limit is reached.
+    fn start(self: &Arc<Self>) {
+        loop {
+            let mut state = self.state.lock();
+            if self.limit != 0 && state.running >= self.limit {
+                return;
+            }
+            let (id, future) = match state.queued.pop_front() {
+                Some(task) => task,
+                None => return,
+            };
+            state.running += 1;
+            drop(state);
+            let mut task = AsyncTask {
+                tasks: self.clone(),
+                id,
+                res: None,
+            };
+            self.executor.spawn(Box::pin(async move {
+                // the task is dropped at the end, finishing it with the result
+                task.res.replace(future.await);
+            }));
+        }
+    }
+
+    /// Sends the result of a finished future and starts the next one.
+    fn finish(self: &Arc<Self>, id: usize, res: Option<Fut::Output>) {
+        let mut state = self.state.lock();
+        state.running -= 1;
+        if self.ordered {
+            state.done.insert(id, res);
+            loop {
+                let next = state.next_out;
+                match state.done.remove(&next) {
+                    Some(res) => state.ready.extend(res),
+                    None => break,
+                }
+                state.next_out += 1;
+            }
+            let send = !mem::replace(&mut state.sending, true);
+            drop(state);
+            if send {
+                self.send_ready();
+            }
+        } else {
+            drop(state);
+            if let (Some(res), Some(cb)) = (res, self.output.upgrade()) {
+                cb.call(res);
+            }
+        }
+        self.start();
+    }
+
+    /// Sends the ordered results until there are none left.
+    ///
+    /// The state isn't locked while calling back, so the callbacks can push new values.
+    fn send_ready(&self) {
+        let guard = SendingGuard(&self.state);
+        loop {
+            let ready = {
+                let mut state = self.state.lock();
+                if state.ready.is_empty() {
+                    state.sending = false;
+                    break;
+                }
+                mem::take(&mut state.ready)
+            };
+            if let Some(cb) = self.output.upgrade() {
+                ready.into_iter().for_each(|res| cb.call(res));
+            }
+        }
+        // another thread can be sending by now, so the flag must not be cleared again
+        mem::forget(guard);
+    }
+}
+
+/// The results of a `Stream::map_parallel` operation.
+#[cfg(all(feature = "std", feature = "rayon"))]
+struct Parallel<R> {
+    state: Mutex<ParallelState<R>>,
+    output: Feeder<R>,
+}
+
+#[cfg(all(feature = "std", feature = "rayon"))]
+struct ParallelState<R> {
+    next_out: usize,
+    // the results waiting for the previous ones, `None` when the closure panicked
+    done: BTreeMap<usize, Option<R>>,
+    // the ordered results waiting to be sent
+    ready: VecDeque<R>,
+    // set while a thread sends the ordered results, so the others leave theirs to it
+    sending: bool,
+}
+
+/// Clears the sending flag if a callback panics while sending the parallel results.
+#[cfg(all(feature = "std", feature = "rayon"))]
+struct ParallelSending<'a, R>(&'a Mutex<ParallelState<R>>);
+
+#[cfg(all(feature = "std", feature = "rayon"))]
+impl<R> Drop for ParallelSending<'_, R> {
+    fn drop(&mut self) {
+        self.0.lock().sending = false;
+    }
+}
+
+#[cfg(all(feature = "std", feature = "rayon"))]
+impl<R> Parallel<R> {
+    /// Records the result of a task and sends the ones that are next in order.
+    fn finish(&self, id: usize, res: Option<R>) {
+        let mut state = self.state.lock();
+        state.done.insert(id, res);
+        loop {
+            let next = state.next_out;
+            match state.done.remove(&next) {
+                Some(res) => state.ready.extend(res),
+                None => break,
+            }
+            state.next_out += 1;
+        }
+        let send = !mem::replace(&mut state.sending, true);
+        drop(state);
+        if !send {
+            return;
+        }
+        // a panicking callback only loses it's own value, and it must not unwind out of the job
+        while panic::catch_unwind(AssertUnwindSafe(|| self.send_ready())).is_err() {
+            let mut state = self.state.lock();
+            if state.sending || state.ready.is_empty() {
+                return;
+            }
+            state.sending = true;
+        }
+    }
+
+    /// Sends the ordered results until there are none left.
+    ///
+    /// The state isn't locked while calling back, so the callbacks can push new values.
+    fn send_ready(&self) {
+        let guard = ParallelSending(&self.state);
+        loop {
+            let res = {
+                let mut state = self.state.lock();
+                match state.ready.pop_front() {
+                    Some(res) => res,
+                    None => {
+                        state.sending = false;
+                        break;
+                    }
+                }
+            };
+            if let Some(cb) = self.output.upgrade() {
+                cb.call(res);
+            }
+        }
+        // another thread can be sending by now, so the flag must not be cleared again
+        mem::forget(guard);
+    }
+}
+
 /// Sends values into a stream.
 ///
-/// This is a restricted version of `Sink` used by `Stream::map_n` and `Stream::scan_n`.
+/// This is used by `Stream::map_n` and `Stream::scan_n`. It only holds a weak reference to the
+/// output stream, so it doesn't keep it alive. The values sent after the output stream is dropped
+/// are discarded.
 #[derive(Debug)]
-pub struct Sender<T>(Sink<T>);
+pub struct Sender<T> {
+    cbs: Feeder<T>,
+}
 
 impl<T> Sender<T> {
     /// Constructs a new Sender from a list of callbacks.
-    fn new(cbs: Arc<Callbacks<T>>) -> Self {
-        Sender(Sink { cbs })
+    fn new(cbs: &Arc<Callbacks<T>>) -> Self {
+        Sender {
+            cbs: Feeder::new(cbs),
+        }
     }
 
     /// Sends a value.
     #[inline]
     pub fn send(&self, val: T) {
-        self.0.send(val)
+        if let Some(cbs) = self.cbs.upgrade() {
+            cbs.call(val)
+        }
     }
 
     /// Sends multiple values.
-    #[inline]
     pub fn feed(&self, iter: impl IntoIterator<Item = T>) {
-        self.0.feed(iter)
+        if let Some(cbs) = self.cbs.upgrade() {
+            iter.into_iter().for_each(|val| cbs.call(val))
+        }
+    }
+
+    /// Checks if the output stream was dropped.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.cbs.strong_count() == 0
+    }
+
+    /// Creates a future that resolves when the output stream is dropped.
+    pub fn closed(&self) -> Closed
+    where
+        T: 'static,
+    {
+        self.cancel_token().cancelled()
+    }
+
+    /// Creates a token that is cancelled when the output stream is dropped.
+    ///
+    /// Unlike the Sender, the token doesn't depend on the output type, so it can be passed to the
+    /// code doing the background work.
+    pub fn cancel_token(&self) -> CancelToken
+    where
+        T: 'static,
+    {
+        let target: Weak<dyn DropWatch> = self.cbs.downgrade();
+        CancelToken { target }
     }
 }
 
 impl<T> Clone for Sender<T> {
     /// Creates a copy of this sender that references the same event source.
     fn clone(&self) -> Self {
-        Sender(self.0.clone())
+        Sender {
+            cbs: self.cbs.clone(),
+        }
+    }
+}
+
+/// A token that tells when the output of an asynchronous operation is no longer needed.
+///
+/// This is created by `Sender::cancel_token`.
+#[derive(Clone)]
+pub struct CancelToken {
+    target: Weak<dyn DropWatch>,
+}
+
+impl CancelToken {
+    /// Checks if the token was cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.target.strong_count() == 0
+    }
+
+    /// Creates a future that resolves when the token is cancelled.
+    pub fn cancelled(&self) -> Closed {
+        Closed::new(self.target.clone())
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CancelToken({})", self.is_cancelled())
     }
 }
 
@@ -814,6 +1835,92 @@ mod tests {
         assert_eq!(rx.try_recv(), Ok(Test(-1)));
     }
 
+    #[test]
+    fn stream_replay_sink() {
+        let sink = ReplaySink::new(2);
+        let rx1 = sink.stream().as_sync_channel(10);
+        sink.feed(vec![1, 2, 3]);
+        let rx2 = sink.stream().as_sync_channel(10);
+        sink.send(MaybeOwned::Borrowed(&4));
+
+        let result1: Vec<_> = rx1.try_iter().collect();
+        let result2: Vec<_> = rx2.try_iter().collect();
+        assert_eq!(result1, [1, 2, 3, 4]);
+        assert_eq!(result2, [2, 3, 4]);
+    }
+
+    #[test]
+    fn stream_behavior_sink() {
+        let sink = BehaviorSink::new("a".to_string());
+        let first = sink.stream().hold(String::new());
+        assert_eq!(first.sample(), "a");
+
+        sink.send("b".to_string());
+        let second = sink.stream().hold(String::new());
+        assert_eq!(first.sample(), "b");
+        assert_eq!(second.sample(), "b");
+
+        // a callback that drops itself while replaying is removed right away
+        sink.stream().observe(|_| false);
+        assert_eq!(sink.0.cbs.len(), 2);
+    }
+
+    #[test]
+    fn stream_replay_derived() {
+        let sink = BehaviorSink::new(5);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_ = calls.clone();
+        let mapped = sink.stream().map(move |x| {
+            calls_.fetch_add(1, Ordering::Relaxed);
+            *x * 10
+        });
+        // the map received the replay when it was created, it's not run again for new callbacks
+        for _ in 0..5 {
+            mapped.observe(|_| ());
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        let last = mapped.hold(0);
+        assert_eq!(last.sample(), 0);
+
+        sink.send(6);
+        assert_eq!(last.sample(), 60);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn stream_replay_reentrant() {
+        let sink = BehaviorSink::new(1);
+        let seen = sink.stream().collect::<Vec<_>>();
+        let sink_ = sink.clone();
+        let replayed = Arc::new(Mutex::new(Vec::new()));
+        let replayed_ = replayed.clone();
+        // subscribes and sends from a callback that is receiving the replay
+        sink.stream().observe(move |x| {
+            replayed_.lock().push(*x);
+            if *x == 1 {
+                sink_.stream().observe(|_| false);
+                sink_.send(2);
+                // the value sent is held back until this replay ends
+                assert_eq!(*replayed_.lock(), [1]);
+            }
+        });
+        assert_eq!(seen.sample(), [1, 2]);
+        assert_eq!(*replayed.lock(), [1, 2]);
+    }
+
+    #[test]
+    fn stream_replay_concurrent_send() {
+        let sink = BehaviorSink::new(0);
+        let sink_ = sink.clone();
+        let sender = std::thread::spawn(move || (1..=300).for_each(|i| sink_.send(i)));
+        let holds: Vec<_> = (0..50).map(|_| sink.stream().hold(-1)).collect();
+        sender.join().unwrap();
+        // the replayed value never overwrites a newer one sent while subscribing
+        for hold in holds {
+            assert_eq!(hold.sample(), 300);
+        }
+    }
+
     #[test]
     fn stream_switch() {
         let stream_sink = Sink::new();
@@ -907,7 +2014,23 @@ mod tests {
         assert_eq!(sink.cbs.len(), 0);
     }
 
-    #[cfg(feature = "crossbeam-utils")]
+    #[test]
+    fn stream_observe_weak() {
+        let sink = Sink::new();
+        let owner = Arc::new(Mutex::new(Vec::new()));
+        sink.stream()
+            .observe_weak(&owner, |vec, x| vec.lock().push(*x));
+
+        sink.feed([1, 2, 3]);
+        assert_eq!(*owner.lock(), [1, 2, 3]);
+        assert_eq!(sink.cbs.len(), 1);
+
+        drop(owner);
+        sink.send(4);
+        assert_eq!(sink.cbs.len(), 0);
+    }
+
+    #[cfg(all(feature = "std", any(feature = "crossbeam-utils", feature = "rayon")))]
     #[test]
     fn stream_send_parallel() {
         use std::thread;
@@ -924,12 +2047,23 @@ mod tests {
         });
         let result = s1.merge(&s2).fold(0, |a, n| a + *n);
 
+        // the global pool may have a single thread, so use one with enough threads
+        #[cfg(feature = "rayon")]
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        #[cfg(feature = "rayon")]
+        let send = |val: &i32| pool.install(|| sink.send_parallel(val));
+        #[cfg(not(feature = "rayon"))]
+        let send = |val: &i32| sink.send_parallel(val);
+
         let t = Instant::now();
-        sink.send_parallel(&10);
+        send(&10);
         assert!(t.elapsed() < Duration::from_millis(100));
         assert_eq!(result.sample(), 31);
-        sink.send_parallel(&1);
-        sink.send_parallel(&13);
+        send(&1);
+        send(&13);
         assert_eq!(result.sample(), 75);
     }
 
@@ -980,7 +2114,7 @@ mod tests {
 
     #[test]
     fn stream_element_at() {
-        use std::sync::mpsc::TryRecvError::Empty;
+        use std::sync::mpsc::TryRecvError::{Disconnected, Empty};
 
         let sink: Sink<i32> = Sink::new();
         let stream1 = sink.stream().element_at(0);
@@ -992,10 +2126,11 @@ mod tests {
 
         sink.feed(&[1, 12, 42, 7, 13]);
 
+        // the streams that got their element are finished, so their channels are closed
         assert_eq!(rx1.try_recv(), Ok(1));
-        assert_eq!(rx1.try_recv(), Err(Empty));
+        assert_eq!(rx1.try_recv(), Err(Disconnected));
         assert_eq!(rx2.try_recv(), Ok(42));
-        assert_eq!(rx2.try_recv(), Err(Empty));
+        assert_eq!(rx2.try_recv(), Err(Disconnected));
         assert_eq!(rx3.try_recv(), Err(Empty));
     }
 
@@ -1019,6 +2154,451 @@ mod tests {
         assert_eq!(result3, [7, 13, -6, 22]);
     }
 
+    #[test]
+    fn stream_take_skip() {
+        let sink: Sink<i32> = Sink::new();
+        let stream1 = sink.stream().take(3);
+        let stream2 = sink.stream().skip(4);
+        let stream3 = sink.stream().step_by(3);
+        let rx1 = stream1.as_sync_channel(10);
+        let rx2 = stream2.as_sync_channel(10);
+        let rx3 = stream3.as_sync_channel(10);
+
+        sink.feed([1, 12, 42, 7, 13, -6, 22]);
+
+        let result1: Vec<_> = rx1.try_iter().collect();
+        let result2: Vec<_> = rx2.try_iter().collect();
+        let result3: Vec<_> = rx3.try_iter().collect();
+        assert_eq!(result1, [1, 12, 42]);
+        assert_eq!(result2, [13, -6, 22]);
+        assert_eq!(result3, [1, 7, 22]);
+        // take(3) detached itself after the third value
+        assert_eq!(sink.cbs.len(), 2);
+    }
+
+    #[test]
+    fn stream_take_skip_while() {
+        let sink: Sink<i32> = Sink::new();
+        let stream1 = sink.stream().take_while(|a| *a > 0);
+        let stream2 = sink.stream().skip_while(|a| *a > 0);
+        let rx1 = stream1.as_sync_channel(10);
+        let rx2 = stream2.as_sync_channel(10);
+
+        sink.feed([1, 12, -42, 7, 13]);
+
+        let result1: Vec<_> = rx1.try_iter().collect();
+        let result2: Vec<_> = rx2.try_iter().collect();
+        assert_eq!(result1, [1, 12]);
+        assert_eq!(result2, [-42, 7, 13]);
+        assert_eq!(sink.cbs.len(), 1);
+    }
+
+    #[test]
+    fn stream_take_skip_until() {
+        let sink: Sink<i32> = Sink::new();
+        let trigger: Sink<()> = Sink::new();
+        let stream1 = sink.stream().take_until(&trigger.stream());
+        let stream2 = sink.stream().skip_until(&trigger.stream());
+        let rx1 = stream1.as_sync_channel(10);
+        let rx2 = stream2.as_sync_channel(10);
+
+        sink.feed([1, 2]);
+        trigger.send(());
+        sink.feed([3, 4]);
+        trigger.send(());
+
+        let result1: Vec<_> = rx1.try_iter().collect();
+        let result2: Vec<_> = rx2.try_iter().collect();
+        assert_eq!(result1, [1, 2]);
+        assert_eq!(result2, [3, 4]);
+        assert_eq!(sink.cbs.len(), 1);
+        assert_eq!(trigger.cbs.len(), 0);
+    }
+
+    #[test]
+    fn stream_partition_route() {
+        #[derive(Debug, PartialEq)]
+        struct NoClone(i32);
+
+        let sink: Sink<NoClone> = Sink::new();
+        let (even, odd) = sink.stream().partition(|x| x.0 % 2 == 0);
+        let s_even = even.fold(0, |a, x| a + x.0);
+        let s_odd = odd.fold(0, |a, x| a + x.0);
+        let routes = sink.stream().route(3, |x| x.0 as usize);
+        let s_routes: Vec<_> = routes.iter().map(|s| s.fold(0, |n, _| n + 1)).collect();
+
+        sink.feed((0..6).map(NoClone));
+        assert_eq!(s_even.sample(), 6);
+        assert_eq!(s_odd.sample(), 9);
+        assert_eq!(
+            s_routes.iter().map(Signal::sample).collect::<Vec<_>>(),
+            [1, 1, 1]
+        );
+
+        // the callback is dropped only when all the outputs are dropped
+        drop(s_routes);
+        let mut routes = routes;
+        let last = routes.pop().unwrap();
+        drop(routes);
+        sink.send(NoClone(0));
+        assert_eq!(sink.cbs.len(), 2);
+        drop(last);
+        sink.send(NoClone(0));
+        assert_eq!(sink.cbs.len(), 1);
+    }
+
+    #[test]
+    fn stream_gate() {
+        let sink = Sink::new();
+        let open = sink.stream().map(|x| *x % 2 == 0).hold(false);
+        let res = sink.stream().gate(&open).collect::<Vec<_>>();
+
+        sink.feed([1, 2, 4, 5, 7, 8]);
+        assert_eq!(res.sample(), [2, 4, 8]);
+    }
+
+    #[test]
+    fn stream_fold_with_history() {
+        let sink = Sink::new();
+        let history = sink.stream().fold_with_history(0, |a, n| a + *n, 2);
+        let present = history.present();
+        let (can_undo, can_redo) = (history.can_undo(), history.can_redo());
+        let undo = history.undo_sink();
+        drop(history);
+
+        assert_eq!((can_undo.sample(), can_redo.sample()), (false, false));
+        sink.feed([1, 2, 3]);
+        assert_eq!(present.sample(), 6);
+        // only two states are kept
+        undo.feed(vec![(); 3]);
+        assert_eq!(present.sample(), 1);
+        assert_eq!((can_undo.sample(), can_redo.sample()), (false, true));
+
+        // a new edit clears the redo states
+        sink.send(10);
+        assert_eq!(present.sample(), 11);
+        assert_eq!((can_undo.sample(), can_redo.sample()), (true, false));
+    }
+
+    #[test]
+    fn stream_fold_with_history_coalesce() {
+        use crate::time::VirtualClock;
+        use core::time::Duration;
+
+        let clock = VirtualClock::new();
+        let sink = Sink::new();
+        let history = sink
+            .stream()
+            .fold_with_history(String::new(), |s, c| s + *c, 10)
+            .coalesce(Duration::from_millis(100), clock.clone());
+        let text = history.present();
+
+        for c in ["h", "e", "y"] {
+            sink.send(c);
+            clock.advance(Duration::from_millis(50));
+        }
+        clock.advance(Duration::from_secs(1));
+        sink.feed([" ", "y", "o"]);
+        assert_eq!(text.sample(), "hey yo");
+        history.undo();
+        assert_eq!(text.sample(), "hey");
+        history.redo();
+        assert_eq!(text.sample(), "hey yo");
+        history.undo();
+        history.undo();
+        assert_eq!(text.sample(), "");
+        assert!(!history.can_undo().sample());
+    }
+
+    #[test]
+    fn stream_pausable_buffered() {
+        use crate::Var;
+
+        let open = Var::new(true);
+        let sink = Sink::new();
+        let res = sink
+            .stream()
+            .pausable_buffered(&open.signal())
+            .collect::<Vec<_>>();
+
+        sink.send(1);
+        open.set(false);
+        sink.feed([2, 3]);
+        assert_eq!(res.sample(), [1]);
+        // flushed as soon as the signal changes
+        open.set(true);
+        assert_eq!(res.sample(), [1, 2, 3]);
+        sink.send(4);
+
+        // signals without storage are only checked on new values
+        let open = Arc::new(AtomicBool::new(false));
+        let open_ = open.clone();
+        let paused = sink
+            .stream()
+            .pausable_buffered(&Signal::from_fn(move || open_.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        sink.feed([5, 6]);
+        open.store(true, Ordering::Relaxed);
+        assert_eq!(paused.sample(), []);
+        sink.send(7);
+        assert_eq!(paused.sample(), [5, 6, 7]);
+        assert_eq!(res.sample(), [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn stream_pausable_buffered_threads() {
+        use crate::Var;
+
+        let open = Var::new(true);
+        let sink = Sink::new();
+        let res = sink
+            .stream()
+            .pausable_buffered(&open.signal())
+            .collect::<Vec<_>>();
+
+        let sink_ = sink.clone();
+        let sender = std::thread::spawn(move || (0..1000).for_each(|i| sink_.send(i)));
+        for i in 0..1000 {
+            open.set(i % 2 == 1);
+        }
+        sender.join().unwrap();
+        // the last change is never lost, even if it happens while a value is being buffered
+        assert_eq!(res.sample(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stream_pausable_buffered_reentrant() {
+        use crate::Var;
+
+        let open = Var::new(true);
+        let sink = Sink::new();
+        let paused = sink.stream().pausable_buffered(&open.signal());
+        let res = paused.collect::<Vec<_>>();
+        // sending back into the source while flushing queues after the rest of the buffer
+        let sink_ = sink.clone();
+        paused.observe(move |v| {
+            if *v % 10 != 0 {
+                sink_.send(*v * 10)
+            }
+        });
+
+        sink.send(1);
+        assert_eq!(res.sample(), [1, 10]);
+        open.set(false);
+        sink.feed([2, 3]);
+        assert_eq!(res.sample(), [1, 10]);
+        open.set(true);
+        assert_eq!(res.sample(), [1, 10, 2, 3, 20, 30]);
+    }
+
+    #[test]
+    fn stream_with_latest_from() {
+        let sink1 = Sink::new();
+        let sink2 = Sink::new();
+        let res = sink1
+            .stream()
+            .with_latest_from(&sink2.stream())
+            .collect::<Vec<_>>();
+
+        sink1.send(1);
+        sink2.send("a");
+        sink2.send("b");
+        sink1.send(2);
+        sink1.send(3);
+        sink2.send("c");
+        assert_eq!(res.sample(), [(2, "b"), (3, "b")]);
+    }
+
+    #[test]
+    fn stream_sender_closed() {
+        use futures::executor::block_on;
+        use std::thread;
+
+        let sink = Sink::new();
+        let senders = Arc::new(Mutex::new(Vec::new()));
+        let senders_ = senders.clone();
+        let out = sink
+            .stream()
+            .map_n(move |n, sender| senders_.lock().push((*n, sender)));
+        let res = out.collect::<Vec<_>>();
+
+        sink.send(1);
+        let (n, sender) = senders.lock().pop().unwrap();
+        let token = sender.cancel_token();
+        assert!(!sender.is_closed());
+        assert!(!token.is_cancelled());
+        sender.send(n * 2);
+        assert_eq!(res.sample(), [2]);
+
+        let handle = thread::spawn(move || block_on(token.cancelled()));
+        drop(out);
+        drop(res);
+        handle.join().unwrap();
+        assert!(sender.is_closed());
+        // the value is discarded
+        sender.send(3);
+        block_on(sender.closed());
+    }
+
+    #[test]
+    fn stream_map_async() {
+        use crate::futures::BoxFuture;
+        use futures::executor::block_on;
+
+        // the spawned futures are kept in a list, so the test can choose when they run
+        let spawned = Arc::new(Mutex::new(Vec::<BoxFuture>::new()));
+        let spawned_ = spawned.clone();
+        let executor = move |fut| spawned_.lock().push(fut);
+        let run = |i: usize| {
+            let fut = spawned.lock().remove(i);
+            block_on(fut);
+        };
+
+        let sink = Sink::new();
+        let unordered = sink
+            .stream()
+            .map_async(executor.clone(), 2, false, |n| {
+                let n = *n;
+                async move { n * 10 }
+            })
+            .collect::<Vec<_>>();
+        let ordered = sink
+            .stream()
+            .map_async(executor, 0, true, |n| {
+                let n = *n;
+                async move { n * 100 }
+            })
+            .collect::<Vec<_>>();
+
+        sink.feed([1, 2, 3, 4]);
+        // the spawns are interleaved: [u1, o1, u2, o2, o3, o4]
+        assert_eq!(spawned.lock().len(), 6);
+        // the second unordered task completes first, and the third one starts
+        run(2);
+        assert_eq!(unordered.sample(), [20]);
+        assert_eq!(spawned.lock().len(), 6);
+        run(0);
+        // [o1, o2, o3, o4, u3, u4]
+        run(4);
+        run(4);
+        assert_eq!(unordered.sample(), [20, 10, 30, 40]);
+
+        // run the ordered tasks backwards
+        for i in (0..4).rev() {
+            assert!(ordered.sample().is_empty());
+            run(i);
+        }
+        assert_eq!(ordered.sample(), [100, 200, 300, 400]);
+        assert!(spawned.lock().is_empty());
+    }
+
+    #[test]
+    fn stream_map_async_panic() {
+        use crate::futures::BoxFuture;
+        use futures::FutureExt;
+        use std::panic::{self, AssertUnwindSafe};
+
+        // runs the futures right away, catching their panics
+        let executor = |fut: BoxFuture| {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| fut.now_or_never()));
+        };
+        let sink = Sink::new();
+        let ordered = sink.stream().map_async(executor, 1, true, |n| {
+            let n = *n;
+            async move {
+                assert!(n != 2, "bad value");
+                n * 10
+            }
+        });
+        let res = ordered.collect::<Vec<_>>();
+        // sends from a callback while the results are being sent
+        let sink_ = sink.clone();
+        ordered.observe(move |n| {
+            if *n == 30 {
+                sink_.send(4);
+            }
+        });
+
+        sink.feed([1, 2, 3]);
+        // the panic freed it's slot and skipped it's value
+        assert_eq!(res.sample(), [10, 30, 40]);
+    }
+
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    #[test]
+    fn stream_map_parallel() {
+        use std::thread;
+        use std::time::Duration;
+
+        let sink = Sink::new();
+        let mapped = sink.stream().map_parallel(|x: i32| {
+            // make the first values finish last
+            thread::sleep(Duration::from_millis(20 - x as u64));
+            x * 2
+        });
+        let rx = mapped.as_sync_channel(20);
+
+        sink.feed(0..20);
+        let result: Vec<_> = rx.iter().take(20).collect();
+        assert_eq!(result, (0..20).map(|x| x * 2).collect::<Vec<_>>());
+    }
+
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    #[test]
+    fn stream_map_parallel_panic() {
+        let sink = Sink::new();
+        let mapped = sink.stream().map_parallel(|x: i32| {
+            assert!(x != 2, "bad value");
+            x * 2
+        });
+        let rx = mapped.as_sync_channel(10);
+
+        sink.feed(0..5);
+        // the panicking value is skipped without stalling the next ones
+        let result: Vec<_> = rx.iter().take(4).collect();
+        assert_eq!(result, [0, 2, 6, 8]);
+
+        // a panicking observer doesn't abort the pool or stop the next results
+        let rx = mapped.as_sync_channel(10);
+        mapped.observe(|x| assert!(*x != 2, "bad result"));
+        sink.feed(0..5);
+        let result: Vec<_> = rx.iter().take(4).collect();
+        assert_eq!(result, [0, 2, 6, 8]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn stream_group_by() {
+        let sink: Sink<(char, i32)> = Sink::new();
+        let groups = Arc::new(Mutex::new(Vec::new()));
+        let groups_ = groups.clone();
+        let grouped = sink.stream().group_by(|(k, _)| *k);
+        grouped.observe(move |group| {
+            let (key, stream) = group.into_owned();
+            groups_
+                .lock()
+                .push((key, stream.map(|x| x.1).collect::<Vec<_>>()));
+        });
+
+        sink.feed(vec![('a', 1), ('b', 2), ('a', 3), ('c', 4), ('b', 5)]);
+        {
+            let groups = groups.lock();
+            let result: Vec<_> = groups.iter().map(|(k, s)| (*k, s.sample())).collect();
+            assert_eq!(
+                result,
+                [('a', vec![1, 3]), ('b', vec![2, 5]), ('c', vec![4])]
+            );
+        }
+
+        // dropping a group expires it, so the next value creates a new one
+        groups.lock().remove(0);
+        sink.send(('a', 6));
+        let groups = groups.lock();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[2].0, 'a');
+        assert_eq!(groups[2].1.sample(), [6]);
+    }
+
     #[cfg(feature = "nightly")]
     #[test]
     fn stream_await() {
@@ -1,15 +1,34 @@
 //! Module that contains the selected version of Mutex/RwLock.
+//!
+//! The backend is chosen from the enabled features, in order: `parking_lot`, `spin` and `std`.
+
+#[cfg(not(any(feature = "std", feature = "spin")))]
+compile_error!(
+    "frappe requires either the `std` or the `spin` feature to be enabled \
+     (since 0.5, builds without the default features must enable `std` explicitly)"
+);
 
 #[cfg(feature = "parking_lot")]
 pub use parking_lot::{Mutex, RwLock};
 
-#[cfg(not(feature = "parking_lot"))]
+#[cfg(all(feature = "spin", not(feature = "parking_lot")))]
+pub use spin::{Mutex, RwLock};
+
+#[cfg(not(any(feature = "parking_lot", feature = "spin")))]
 pub use self::wrapper::{Mutex, RwLock};
 
-#[cfg(not(feature = "parking_lot"))]
+#[cfg(feature = "std")]
+pub use arc_swap::ArcSwap;
+
+#[cfg(not(feature = "std"))]
+pub use self::swap::ArcSwap;
+
+#[cfg(not(any(feature = "parking_lot", feature = "spin")))]
 #[allow(dead_code)]
 mod wrapper {
-    use std::sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+    //! Wrappers over the std locks that ignore poisoning, like the other backends.
+
+    use std::sync::{MutexGuard, PoisonError, RwLockReadGuard, RwLockWriteGuard};
 
     #[derive(Debug, Default)]
     pub struct Mutex<T>(std::sync::Mutex<T>);
@@ -21,12 +40,12 @@ mod wrapper {
         }
 
         #[inline]
-        pub fn lock(&self) -> MutexGuard<T> {
-            self.0.lock().unwrap()
+        pub fn lock(&self) -> MutexGuard<'_, T> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
         }
 
         #[inline]
-        pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
             self.0.try_lock().ok()
         }
     }
@@ -41,23 +60,79 @@ mod wrapper {
         }
 
         #[inline]
-        pub fn read(&self) -> RwLockReadGuard<T> {
-            self.0.read().unwrap()
+        pub fn read(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
         }
 
         #[inline]
-        pub fn write(&self) -> RwLockWriteGuard<T> {
-            self.0.write().unwrap()
+        pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
         }
 
         #[inline]
-        pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
             self.0.try_read().ok()
         }
 
         #[inline]
-        pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
             self.0.try_write().ok()
         }
     }
 }
+
+/// Fallback for `arc_swap::ArcSwap` on `no_std`.
+///
+/// Readers hold the lock only while cloning the `Arc`, so it's never locked during a callback.
+#[cfg(not(feature = "std"))]
+mod swap {
+    use super::RwLock;
+    use alloc::sync::Arc;
+    use core::fmt;
+
+    #[derive(Default)]
+    pub struct ArcSwap<T>(RwLock<Arc<T>>);
+
+    impl<T> ArcSwap<T> {
+        #[inline]
+        pub fn load(&self) -> Arc<T> {
+            self.0.read().clone()
+        }
+
+        #[inline]
+        pub fn load_full(&self) -> Arc<T> {
+            self.load()
+        }
+
+        /// Replaces the value.
+        pub fn store(&self, val: Arc<T>) {
+            // the old value is dropped after the lock is released
+            let old = core::mem::replace(&mut *self.0.write(), val);
+            drop(old);
+        }
+
+        /// Replaces the value with one computed from the current value.
+        pub fn rcu<R, F>(&self, mut f: F)
+        where
+            F: FnMut(&Arc<T>) -> R,
+            R: Into<Arc<T>>,
+        {
+            loop {
+                let cur = self.load();
+                let new = f(&cur).into();
+                let mut val = self.0.write();
+                if Arc::ptr_eq(&val, &cur) {
+                    // `cur` keeps the old value alive until the lock is released
+                    *val = new;
+                    return;
+                }
+            }
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for ArcSwap<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.read().fmt(f)
+        }
+    }
+}
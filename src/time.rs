@@ -0,0 +1,193 @@
+//! Time sources for continuous-time signals.
+//!
+//! Operations like `Signal::integral` and `Signal::derivative` read the current time from a
+//! `Clock` every time they're sampled. `SystemClock` follows the real time, and `VirtualClock`
+//! is advanced manually, which is useful for simulations and tests.
+//!
+//! # Example
+//! ```
+//! use frappe::Signal;
+//! use frappe::time::VirtualClock;
+//! use std::time::Duration;
+//!
+//! let clock = VirtualClock::new();
+//! let speed = Signal::constant(2.0);
+//! let position = speed.integral(clock.clone());
+//!
+//! position.sample();
+//! clock.advance(Duration::from_secs(3));
+//! assert_eq!(position.sample(), 6.0);
+//! ```
+
+use crate::sync::Mutex;
+use alloc::sync::Arc;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the time elapsed since the clock's starting point.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    #[inline]
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// A clock that follows the real time.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    /// Creates a clock that starts counting from now.
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    #[inline]
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when it's advanced manually.
+///
+/// All the clones of a virtual clock share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    time: Arc<Mutex<Duration>>,
+}
+
+impl VirtualClock {
+    /// Creates a clock starting at zero.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, dt: Duration) {
+        *self.time.lock() += dt;
+    }
+
+    /// Sets the current time.
+    pub fn set(&self, time: Duration) {
+        *self.time.lock() = time;
+    }
+}
+
+impl Clock for VirtualClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        *self.time.lock()
+    }
+}
+
+/// A value that can be added and scaled, like the quantities that change over time.
+///
+/// This is used to integrate and differentiate signals.
+pub trait VectorSpace: Sized {
+    /// Returns the zero value.
+    fn zero() -> Self;
+    /// Adds two values.
+    fn add(&self, other: &Self) -> Self;
+    /// Subtracts two values.
+    fn sub(&self, other: &Self) -> Self;
+    /// Multiplies the value by a scalar.
+    fn scale(&self, factor: f64) -> Self;
+}
+
+impl VectorSpace for f64 {
+    #[inline]
+    fn zero() -> Self {
+        0.0
+    }
+
+    #[inline]
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    #[inline]
+    fn sub(&self, other: &Self) -> Self {
+        self - other
+    }
+
+    #[inline]
+    fn scale(&self, factor: f64) -> Self {
+        self * factor
+    }
+}
+
+impl VectorSpace for f32 {
+    #[inline]
+    fn zero() -> Self {
+        0.0
+    }
+
+    #[inline]
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    #[inline]
+    fn sub(&self, other: &Self) -> Self {
+        self - other
+    }
+
+    #[inline]
+    fn scale(&self, factor: f64) -> Self {
+        (f64::from(*self) * factor) as f32
+    }
+}
+
+macro_rules! impl_vector_space_tuple {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: VectorSpace),+> VectorSpace for ($($t,)+) {
+            #[inline]
+            fn zero() -> Self {
+                ($($t::zero(),)+)
+            }
+
+            #[inline]
+            fn add(&self, other: &Self) -> Self {
+                ($(self.$i.add(&other.$i),)+)
+            }
+
+            #[inline]
+            fn sub(&self, other: &Self) -> Self {
+                ($(self.$i.sub(&other.$i),)+)
+            }
+
+            #[inline]
+            fn scale(&self, factor: f64) -> Self {
+                ($(self.$i.scale(factor),)+)
+            }
+        }
+    };
+}
+
+impl_vector_space_tuple!(A 0, B 1);
+impl_vector_space_tuple!(A 0, B 1, C 2);
+impl_vector_space_tuple!(A 0, B 1, C 2, D 3);
@@ -2,13 +2,19 @@
 
 #[cfg(feature = "either")]
 pub use either::Either;
+#[cfg(feature = "std")]
 pub use maybe_owned::MaybeOwned;
 
+#[cfg(not(feature = "std"))]
+mod maybe_owned;
+#[cfg(not(feature = "std"))]
+pub use crate::types::maybe_owned::MaybeOwned;
+
 mod callbacks;
-pub(crate) use crate::types::callbacks::Callbacks;
+pub(crate) use crate::types::callbacks::{Callbacks, DropWatch, Feeder};
 
 mod storage;
-pub(crate) use crate::types::storage::Storage;
+pub(crate) use crate::types::storage::{Storage, Watch};
 
 /// Generic sum type of two elements.
 ///
@@ -1,18 +1,27 @@
 //! Callback container for Stream.
 
-use crate::sync::RwLock;
-use maybe_owned::MaybeOwned;
-use std::fmt;
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{ArcSwap, Mutex};
+use crate::types::MaybeOwned;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Waker;
 
-#[cfg(feature = "crossbeam-utils")]
+#[cfg(all(feature = "std", feature = "crossbeam-utils", not(feature = "rayon")))]
 use crossbeam_utils::thread;
 
+/// Boxed callback closure stored inside a `FnCell`.
+type CallbackFn<T> = Box<dyn Fn(MaybeOwned<'_, T>) -> bool + Send + Sync>;
+
 /// Function that becomes uncallable after it returns false.
 ///
 /// Callbacks use a `MaybeOwned<T>` argument so we can choose at runtime if we will send a ref or an owned value.
 struct FnCell<T> {
-    f: Box<dyn Fn(MaybeOwned<'_, T>) -> bool + Send + Sync>,
+    f: CallbackFn<T>,
     alive: AtomicBool,
 }
 
@@ -57,10 +66,126 @@ impl<T> fmt::Debug for FnCell<T> {
     }
 }
 
+/// The last values sent to a callback list, replayed to new callbacks.
+///
+/// This is a trait object so `Callbacks<T>` stays `Send + Sync` for any `T`.
+trait ReplayBuffer<T>: Send + Sync {
+    /// Records a value, and runs the closure before the buffer is unlocked.
+    fn record(&self, val: &T, locked: &mut dyn FnMut());
+
+    /// Sends the recorded values to a new callback, returning `false` if it died.
+    ///
+    /// The callback is added to the list with `insert` while the buffer is locked, and the values
+    /// sent to it while it receives the replay are queued and sent after it.
+    fn replay(&self, f: CallbackFn<T>, insert: &mut dyn FnMut(Arc<FnCell<T>>)) -> bool;
+}
+
+/// A `ReplayBuffer` with a fixed capacity.
+struct Replay<T> {
+    vals: Mutex<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T: Clone + Send + 'static> ReplayBuffer<T> for Replay<T> {
+    fn record(&self, val: &T, locked: &mut dyn FnMut()) {
+        let mut vals = self.vals.lock();
+        if self.capacity > 0 {
+            if vals.len() == self.capacity {
+                vals.pop_front();
+            }
+            vals.push_back(val.clone());
+        }
+        locked();
+    }
+
+    fn replay(&self, f: CallbackFn<T>, insert: &mut dyn FnMut(Arc<FnCell<T>>)) -> bool {
+        let gate = Arc::new(ReplayGate {
+            f,
+            queue: Mutex::new(Some(VecDeque::new())),
+            replaying: AtomicBool::new(true),
+            alive: AtomicBool::new(true),
+        });
+        let gate_ = gate.clone();
+        let cell = Arc::new(FnCell::new(move |arg| gate_.call(arg)));
+        // every value is either copied here or sent to the new callback, but not both
+        let vals: Vec<T> = {
+            let vals = self.vals.lock();
+            insert(cell.clone());
+            vals.iter().cloned().collect()
+        };
+        let alive = gate.replay(vals);
+        if !alive {
+            cell.alive.store(false, Ordering::Relaxed);
+        }
+        alive
+    }
+}
+
+/// Holds back the values sent to a new callback until it has received the replay.
+struct ReplayGate<T> {
+    f: CallbackFn<T>,
+    // the values received while replaying, `None` after the replay ends
+    queue: Mutex<Option<VecDeque<T>>>,
+    replaying: AtomicBool,
+    alive: AtomicBool,
+}
+
+impl<T: Clone> ReplayGate<T> {
+    /// Calls the closure, or queues the value if the replay is still in progress.
+    fn call(&self, arg: MaybeOwned<'_, T>) -> bool {
+        if self.replaying.load(Ordering::Acquire) {
+            if let Some(queue) = self.queue.lock().as_mut() {
+                queue.push_back(arg.into_owned());
+                return self.alive.load(Ordering::Relaxed);
+            }
+        }
+        self.alive.load(Ordering::Relaxed) && (self.f)(arg)
+    }
+
+    /// Sends the replayed values and then the queued ones, returning `false` if the closure died.
+    fn replay(&self, vals: Vec<T>) -> bool {
+        let mut alive = true;
+        for val in vals {
+            alive = alive && (self.f)(MaybeOwned::Owned(val));
+        }
+        loop {
+            let val = {
+                let mut queue = self.queue.lock();
+                match queue.as_mut().and_then(VecDeque::pop_front) {
+                    Some(val) => val,
+                    None => {
+                        // the queue is closed while locked, so no value is left behind
+                        *queue = None;
+                        self.replaying.store(false, Ordering::Release);
+                        break;
+                    }
+                }
+            };
+            alive = alive && (self.f)(MaybeOwned::Owned(val));
+        }
+        self.alive.store(alive, Ordering::Relaxed);
+        alive
+    }
+}
+
+/// The callback list. It's never modified in place, a new copy is swapped in instead.
+type CellList<T> = Vec<Arc<FnCell<T>>>;
+
 /// A collection of callbacks.
-#[derive(Debug)]
+///
+/// Senders read the current callback list without locking, while adding or removing callbacks
+/// swaps in an updated copy of the list. The dead callbacks are removed from the list after they
+/// return false, and freed when the last sender still using an old copy of the list finishes.
+///
+/// The objects that send values into the list hold a `Feeder`. When the last one is dropped the
+/// list ends: no more values can arrive, so all the callbacks are dropped, including the ones
+/// added later.
 pub struct Callbacks<T> {
-    fs: RwLock<Vec<FnCell<T>>>,
+    fs: ArcSwap<CellList<T>>,
+    buffer: Option<Arc<dyn ReplayBuffer<T>>>,
+    dropped: Mutex<Vec<Waker>>,
+    feeders: AtomicUsize,
+    ended: AtomicBool,
 }
 
 impl<T> Callbacks<T> {
@@ -68,50 +193,93 @@ impl<T> Callbacks<T> {
     pub fn new() -> Self {
         Callbacks {
             fs: Default::default(),
+            buffer: None,
+            dropped: Default::default(),
+            feeders: AtomicUsize::new(0),
+            ended: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates an empty callback list and the first feeder for it.
+    pub fn with_feeder() -> (Arc<Self>, Feeder<T>) {
+        let cbs = Arc::new(Callbacks::new());
+        let feeder = Feeder::new(&cbs);
+        (cbs, feeder)
+    }
+
+    /// Creates an empty callback list that replays the last `capacity` values to new callbacks.
+    ///
+    /// The values must be sent using `Callbacks::call_replay` to be recorded.
+    pub fn with_replay(capacity: usize, initial: impl IntoIterator<Item = T>) -> Self
+    where
+        T: Clone + Send + 'static,
+    {
+        let mut vals: VecDeque<T> = initial.into_iter().collect();
+        while vals.len() > capacity {
+            vals.pop_front();
+        }
+        Callbacks {
+            fs: Default::default(),
+            buffer: Some(Arc::new(Replay {
+                vals: Mutex::new(vals),
+                capacity,
+            })),
+            dropped: Default::default(),
+            feeders: AtomicUsize::new(0),
+            ended: AtomicBool::new(false),
         }
     }
 
     /// Adds a new closure to the callback list.
+    ///
+    /// If this list replays values, they're sent to the closure first. The values sent while the
+    /// replay is in progress are held back and sent after it.
     pub fn push<F>(&self, cb: F)
     where
         F: Fn(MaybeOwned<'_, T>) -> bool + Send + Sync + 'static,
     {
-        self.fs.write().push(FnCell::new(cb))
+        match &self.buffer {
+            Some(buffer) => {
+                if !buffer.replay(Box::new(cb), &mut |cell| self.insert(cell)) {
+                    self.cleanup();
+                }
+            }
+            None => self.insert(Arc::new(FnCell::new(cb))),
+        }
+        // a closure added after the end is never called, but it still gets the replay
+        if self.ended.load(Ordering::SeqCst) {
+            self.fs.store(Default::default());
+        }
+    }
+
+    /// Drops the callbacks after the last feeder is gone.
+    fn end(&self) {
+        self.ended.store(true, Ordering::SeqCst);
+        self.fs.store(Default::default());
+    }
+
+    /// Swaps in a new list with the cell added at the end.
+    fn insert(&self, cell: Arc<FnCell<T>>) {
+        self.fs.rcu(|fs| {
+            let mut fs = CellList::clone(fs);
+            fs.push(cell.clone());
+            fs
+        });
     }
 
     /// Sends an owned value.
     ///
     /// This sends a ref to the first N-1 callbacks, and the owned value to the last.
     pub fn call_owned(&self, arg: T) {
-        let fs = self.fs.read();
-        let n = fs.len();
-
-        let mut i = 0;
-        let mut all_alive = true;
-        for _ in 1..n {
-            all_alive &= fs[i].call(MaybeOwned::Borrowed(&arg));
-            i += 1;
-        }
-        if n > 0 {
-            all_alive &= fs[i].call(MaybeOwned::Owned(arg));
-        }
-        drop(fs);
-
-        if !all_alive {
+        // a full `Arc` is taken, so the list isn't borrowed from `ArcSwap` while the callbacks run
+        if !Self::call_each(&self.fs.load_full(), MaybeOwned::Owned(arg)) {
             self.cleanup();
         }
     }
 
     /// Sends a value by reference.
     pub fn call_ref(&self, arg: &T) {
-        let all_alive = self
-            .fs
-            .read()
-            .iter()
-            .map(|f| f.call(MaybeOwned::Borrowed(arg)))
-            .fold(true, |a, alive| a & alive);
-
-        if !all_alive {
+        if !Self::call_each(&self.fs.load_full(), MaybeOwned::Borrowed(arg)) {
             self.cleanup();
         }
     }
@@ -128,64 +296,129 @@ impl<T> Callbacks<T> {
         }
     }
 
+    /// Sends a value and records it on the replay buffer.
+    ///
+    /// A copy of the value is recorded before it's sent. If this list has no replay buffer this
+    /// is the same as `Callbacks::call`.
+    pub fn call_replay<'a>(&self, arg: impl Into<MaybeOwned<'a, T>>)
+    where
+        T: 'a,
+    {
+        let buffer = match &self.buffer {
+            Some(buffer) => buffer,
+            None => return self.call(arg),
+        };
+        let arg = arg.into();
+        // the list is read while the buffer is locked, so a callback being added gets the value
+        // from the replay or from here, but not both
+        let mut fs = None;
+        buffer.record(&arg, &mut || fs = Some(self.fs.load_full()));
+        if !Self::call_each(&fs.unwrap(), arg) {
+            self.cleanup();
+        }
+    }
+
+    /// Calls the functions in order, returning `false` if any of them died.
+    ///
+    /// An owned value is sent as a ref to the first N-1 callbacks, and moved into the last.
+    fn call_each(fs: &[Arc<FnCell<T>>], arg: MaybeOwned<'_, T>) -> bool {
+        let (last, rest) = match fs.split_last() {
+            Some(split) => split,
+            None => return true,
+        };
+        let mut all_alive = true;
+        for f in rest {
+            all_alive &= f.call(MaybeOwned::Borrowed(&arg));
+        }
+        all_alive & last.call(arg)
+    }
+
     /// Sends a value using multiple threads.
-    #[cfg(feature = "crossbeam-utils")]
+    #[cfg(all(feature = "std", any(feature = "crossbeam-utils", feature = "rayon")))]
     pub fn call_parallel(&self, arg: &T)
     where
         T: Sync,
     {
-        let fs = self.fs.read();
-        let n = fs.len();
-        // nothing to do
-        if n == 0 {
-            return;
-        }
-        // only 1 callback, just run it on this thread
-        if n == 1 {
-            if !fs[0].call(MaybeOwned::Borrowed(arg)) {
-                self.cleanup();
-            }
-            return;
+        let fs = self.fs.load_full();
+        let all_alive = match fs.len() {
+            // nothing to do
+            0 => true,
+            // only 1 callback, just run it on this thread
+            1 => fs[0].call(MaybeOwned::Borrowed(arg)),
+            // 2+ callbacks, we need more threads
+            _ => Self::call_each_parallel(&fs, arg),
+        };
+        // after all threads finished, continue with the cleanup
+        drop(fs);
+        if !all_alive {
+            self.cleanup();
         }
-        // 2+ callbacks, we need more threads
+    }
+
+    /// Calls the functions using the rayon thread pool.
+    #[cfg(all(feature = "std", feature = "rayon"))]
+    fn call_each_parallel(fs: &[Arc<FnCell<T>>], arg: &T) -> bool
+    where
+        T: Sync,
+    {
+        use rayon::prelude::*;
+
+        fs.par_iter()
+            .map(|f| f.call(MaybeOwned::Borrowed(arg)))
+            .reduce(|| true, |a, alive| a & alive)
+    }
+
+    /// Calls the functions by spawning a thread for each one.
+    #[cfg(all(feature = "std", feature = "crossbeam-utils", not(feature = "rayon")))]
+    fn call_each_parallel(fs: &[Arc<FnCell<T>>], arg: &T) -> bool
+    where
+        T: Sync,
+    {
         let all_alive = AtomicBool::new(true);
         thread::scope(|scope| {
             let all_alive = &all_alive;
-
-            let mut i = 0;
+            let (last, rest) = fs.split_last().unwrap();
             // spawn N-1 threads
-            for _ in 1..n {
-                let f = &fs[i];
+            for f in rest {
                 scope.spawn(move |_| {
                     if !f.call(MaybeOwned::Borrowed(arg)) {
                         all_alive.store(false, Ordering::Relaxed);
                     }
                 });
-                i += 1;
             }
             // run the last callback on current thread
-            if !fs[i].call(MaybeOwned::Borrowed(arg)) {
+            if !last.call(MaybeOwned::Borrowed(arg)) {
                 all_alive.store(false, Ordering::Relaxed);
             }
         })
         .unwrap();
-        // after all threads finished, continue with the cleanup
-        drop(fs);
-        if all_alive.load(Ordering::Relaxed) {
-            self.cleanup();
-        }
+        all_alive.load(Ordering::Relaxed)
     }
 
     /// Removes the dead callbacks.
+    ///
+    /// This retries until the new list is swapped in, so it can't be lost to a concurrent update.
     fn cleanup(&self) {
-        if let Some(mut fs) = self.fs.try_write() {
-            fs.retain(FnCell::is_alive);
-        }
+        self.fs.rcu(|fs| {
+            fs.iter()
+                .filter(|f| f.is_alive())
+                .cloned()
+                .collect::<CellList<T>>()
+        });
     }
 
     #[cfg(test)]
     pub(crate) fn len(&self) -> usize {
-        self.fs.read().len()
+        self.fs.load().len()
+    }
+}
+
+impl<T> fmt::Debug for Callbacks<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("fs", &self.fs.load())
+            .field("replay", &self.buffer.is_some())
+            .finish()
     }
 }
 
@@ -194,3 +427,155 @@ impl<T> Default for Callbacks<T> {
         Callbacks::new()
     }
 }
+
+impl<T> Drop for Callbacks<T> {
+    fn drop(&mut self) {
+        let wakers = mem::take(&mut *self.dropped.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// A weak reference to a callback list, held by the objects that send values into it.
+///
+/// The list ends when the last feeder is dropped.
+pub struct Feeder<T>(Weak<Callbacks<T>>);
+
+impl<T> Feeder<T> {
+    /// Creates a new feeder for a callback list.
+    pub fn new(cbs: &Arc<Callbacks<T>>) -> Self {
+        cbs.feeders.fetch_add(1, Ordering::SeqCst);
+        Feeder(Arc::downgrade(cbs))
+    }
+
+    /// Obtains the callback list if it's still alive.
+    #[inline]
+    pub fn upgrade(&self) -> Option<Arc<Callbacks<T>>> {
+        self.0.upgrade()
+    }
+
+    /// Gets the number of strong references to the callback list.
+    #[inline]
+    pub fn strong_count(&self) -> usize {
+        self.0.strong_count()
+    }
+
+    /// Obtains the weak reference to the callback list.
+    #[inline]
+    pub fn downgrade(&self) -> Weak<Callbacks<T>> {
+        self.0.clone()
+    }
+}
+
+impl<T> Clone for Feeder<T> {
+    fn clone(&self) -> Self {
+        if let Some(cbs) = self.0.upgrade() {
+            cbs.feeders.fetch_add(1, Ordering::SeqCst);
+        }
+        Feeder(self.0.clone())
+    }
+}
+
+impl<T> Drop for Feeder<T> {
+    fn drop(&mut self) {
+        if let Some(cbs) = self.0.upgrade() {
+            if cbs.feeders.fetch_sub(1, Ordering::SeqCst) == 1 {
+                cbs.end();
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Feeder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Feeder(Callbacks@{:p})", self.0.as_ptr())
+    }
+}
+
+/// An object that can wake a task when it's dropped.
+pub trait DropWatch: Send + Sync {
+    /// Registers a waker that will be woken when this object is dropped.
+    fn watch_drop(&self, waker: &Waker);
+}
+
+impl<T> DropWatch for Callbacks<T> {
+    fn watch_drop(&self, waker: &Waker) {
+        let mut wakers = self.dropped.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn callbacks_reentrant() {
+        let cbs = Arc::new(Callbacks::<i32>::new());
+        let count = Arc::new(AtomicUsize::new(0));
+        let (cbs_, count_) = (Arc::downgrade(&cbs), count.clone());
+        // adds a new callback while the list is being called, then dies
+        cbs.push(move |_| {
+            let count = count_.clone();
+            cbs_.upgrade().unwrap().push(move |_| {
+                count.fetch_add(1, Ordering::Relaxed);
+                true
+            });
+            false
+        });
+
+        cbs.call(1);
+        assert_eq!(cbs.len(), 1);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+        cbs.call(2);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(feature = "std", any(feature = "crossbeam-utils", feature = "rayon")))]
+    #[test]
+    fn callbacks_parallel_cleanup() {
+        let cbs = Callbacks::<i32>::new();
+        cbs.push(|_| false);
+        cbs.call_parallel(&1);
+        assert_eq!(cbs.len(), 0);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        for alive in [true, false, true] {
+            let count = count.clone();
+            cbs.push(move |_| {
+                count.fetch_add(1, Ordering::Relaxed);
+                alive
+            });
+        }
+        cbs.call_parallel(&1);
+        assert_eq!(cbs.len(), 2);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+        cbs.call_parallel(&2);
+        assert_eq!(count.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn callbacks_cleanup_concurrent() {
+        let cbs = Arc::new(Callbacks::<i32>::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cbs = cbs.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        cbs.push(|_| false);
+                        cbs.call(0);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        cbs.call(0);
+        assert_eq!(cbs.len(), 0);
+    }
+}
@@ -0,0 +1,86 @@
+//! Minimal version of the `maybe_owned::MaybeOwned` type for `no_std`.
+//!
+//! The `maybe-owned` crate requires `std`, so this is a separate type with only the parts of the
+//! API that the library uses.
+
+use core::fmt;
+use core::ops::Deref;
+
+/// Either an owned value or a reference to it.
+pub enum MaybeOwned<'a, T> {
+    /// An owned value.
+    Owned(T),
+    /// A borrowed value.
+    Borrowed(&'a T),
+}
+
+impl<T> MaybeOwned<'_, T> {
+    /// Returns true if the value is owned.
+    pub fn is_owned(&self) -> bool {
+        matches!(self, MaybeOwned::Owned(_))
+    }
+
+    /// Extracts the owned value, cloning it if it's borrowed.
+    pub fn into_owned(self) -> T
+    where
+        T: Clone,
+    {
+        match self {
+            MaybeOwned::Owned(v) => v,
+            MaybeOwned::Borrowed(r) => r.clone(),
+        }
+    }
+}
+
+impl<T> Deref for MaybeOwned<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            MaybeOwned::Owned(v) => v,
+            MaybeOwned::Borrowed(r) => r,
+        }
+    }
+}
+
+impl<T> AsRef<T> for MaybeOwned<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> From<T> for MaybeOwned<'_, T> {
+    fn from(val: T) -> Self {
+        MaybeOwned::Owned(val)
+    }
+}
+
+impl<'a, T> From<&'a T> for MaybeOwned<'a, T> {
+    fn from(val: &'a T) -> Self {
+        MaybeOwned::Borrowed(val)
+    }
+}
+
+impl<T: Clone> Clone for MaybeOwned<'_, T> {
+    fn clone(&self) -> Self {
+        match self {
+            MaybeOwned::Owned(v) => MaybeOwned::Owned(v.clone()),
+            MaybeOwned::Borrowed(r) => MaybeOwned::Borrowed(r),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MaybeOwned<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaybeOwned::Owned(v) => f.debug_tuple("Owned").field(v).finish(),
+            MaybeOwned::Borrowed(r) => f.debug_tuple("Borrowed").field(r).finish(),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for MaybeOwned<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
@@ -1,10 +1,15 @@
 //! Storage cell used by Signal.
 
-use crate::sync::RwLock;
+use crate::signal::SampleError;
+use crate::sync::{Mutex, RwLock};
+use alloc::vec::Vec;
+use core::mem;
+use core::task::Waker;
 
 /// Storage cell for shared signal values.
 pub struct Storage<T> {
     val: RwLock<Option<T>>,
+    wakers: Mutex<Vec<Waker>>,
 }
 
 const ERR_EMPTY: &str = "storage empty";
@@ -14,20 +19,22 @@ impl<T> Storage<T> {
     pub fn new(val: T) -> Self {
         Storage {
             val: RwLock::new(Some(val)),
+            wakers: Default::default(),
         }
     }
 
-    /// Gets the value by cloning.
-    pub fn get(&self) -> T
+    /// Gets the value by cloning, failing if the storage is empty.
+    pub fn try_get(&self) -> Result<T, SampleError>
     where
         T: Clone,
     {
-        self.val.read().clone().expect(ERR_EMPTY)
+        self.val.read().clone().ok_or(SampleError::EmptyStorage)
     }
 
     /// Sets the value.
     pub fn set(&self, val: T) {
         *self.val.write() = Some(val);
+        self.notify();
     }
 
     /// Maps the stored value in place.
@@ -38,6 +45,8 @@ impl<T> Storage<T> {
         let mut st = self.val.write();
         let old = st.take().expect(ERR_EMPTY);
         *st = Some(f(old));
+        drop(st);
+        self.notify();
     }
 
     /// Same as `replace` but it also returns the new value.
@@ -50,9 +59,26 @@ impl<T> Storage<T> {
         let old = st.take().expect(ERR_EMPTY);
         let new = f(old);
         *st = Some(new.clone());
+        drop(st);
+        self.notify();
         new
     }
 
+    /// Same as `replace_fetch`, but it fails instead of panicking if the storage is empty.
+    pub fn try_replace_fetch<F>(&self, f: F) -> Result<T, SampleError>
+    where
+        F: FnOnce(T) -> T,
+        T: Clone,
+    {
+        let mut st = self.val.write();
+        let old = st.take().ok_or(SampleError::EmptyStorage)?;
+        let new = f(old);
+        *st = Some(new.clone());
+        drop(st);
+        self.notify();
+        Ok(new)
+    }
+
     /// A `replace` version with cloning.
     pub fn replace_clone<F>(&self, f: F)
     where
@@ -62,6 +88,39 @@ impl<T> Storage<T> {
         let mut st = self.val.write();
         let old = st.clone().expect(ERR_EMPTY);
         *st = Some(f(old));
+        drop(st);
+        self.notify();
+    }
+
+    /// Reads the value by reference.
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(self.val.read().as_ref().expect(ERR_EMPTY))
+    }
+
+    /// Modifies the stored value in place.
+    pub fn modify<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let res = f(self.val.write().as_mut().expect(ERR_EMPTY));
+        self.notify();
+        res
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn watchers(&self) -> usize {
+        self.wakers.lock().len()
+    }
+
+    /// Wakes all the tasks waiting for a change on this storage.
+    fn notify(&self) {
+        let wakers = mem::take(&mut *self.wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
     }
 }
 
@@ -70,6 +129,22 @@ impl<T> Default for Storage<T> {
     fn default() -> Self {
         Storage {
             val: Default::default(),
+            wakers: Default::default(),
+        }
+    }
+}
+
+/// An object that can wake a task when it's value changes.
+pub trait Watch: Send + Sync {
+    /// Registers a waker that will be woken on the next change.
+    fn watch(&self, waker: &Waker);
+}
+
+impl<T: Send + Sync> Watch for Storage<T> {
+    fn watch(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
         }
     }
 }
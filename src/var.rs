@@ -0,0 +1,399 @@
+//! The Var type.
+//!
+//! A `Var` is a mutable value that can be read as a `Signal`. It replaces the pattern of creating
+//! a `Sink`, holding it's stream into a `Signal`, and then keeping both objects around.
+//! Every write is also sent to the `Var`'s change stream, so it can be used as an event source too.
+//! Parts of a var's value can be bound to other vars using `Var::lens`.
+//!
+//! # Example
+//! ```
+//! use frappe::Var;
+//!
+//! let var = Var::new(1);
+//! let double = var.signal().map(|x| x * 2);
+//! let changes = var.stream().collect::<Vec<_>>();
+//!
+//! var.set(20);
+//! var.update(|x| x + 1);
+//! assert_eq!(double.sample(), 42);
+//! assert_eq!(changes.sample(), [20, 21]);
+//! ```
+
+use crate::signal::Signal;
+use crate::stream::{Sink, Stream};
+use crate::sync::Mutex;
+use crate::types::{Storage, Watch};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::fmt;
+use core::mem;
+
+/// The object that stores the value of a `Var`.
+trait VarSource<T>: Send + Sync {
+    /// Reads the value by reference.
+    fn read(&self, f: &mut dyn FnMut(&T));
+    /// Modifies the value in place and notifies the change.
+    fn modify(&self, f: &mut dyn FnMut(&mut T));
+    /// Creates a stream that receives the new value on every change.
+    fn stream(&self) -> Stream<T>;
+    /// Obtains the object that notifies the changes to signals.
+    fn watch(&self) -> Arc<dyn Watch>;
+}
+
+/// A var that owns it's storage.
+struct Root<T> {
+    storage: Arc<Storage<T>>,
+    sink: Sink<T>,
+    changes: Mutex<Changes<T>>,
+}
+
+/// The changes of a root var waiting to be sent to it's stream.
+struct Changes<T> {
+    queue: VecDeque<T>,
+    // set while a thread sends the changes, so the others leave theirs to it
+    sending: bool,
+}
+
+/// Clears the sending flag if a callback panics while sending the changes.
+struct SendingGuard<'a, T>(&'a Mutex<Changes<T>>);
+
+impl<T> Drop for SendingGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.lock().sending = false;
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Root<T> {
+    /// Sends the queued changes until there are none left.
+    ///
+    /// No lock is held while calling back, so the callbacks can write the var again.
+    fn send_changes(&self) {
+        let mut changes = self.changes.lock();
+        if mem::replace(&mut changes.sending, true) {
+            return;
+        }
+        drop(changes);
+        let guard = SendingGuard(&self.changes);
+        loop {
+            let val = {
+                let mut changes = self.changes.lock();
+                match changes.queue.pop_front() {
+                    Some(val) => val,
+                    None => {
+                        changes.sending = false;
+                        break;
+                    }
+                }
+            };
+            self.sink.send(val);
+        }
+        // another thread can be sending by now, so the flag must not be cleared again
+        mem::forget(guard);
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> VarSource<T> for Root<T> {
+    fn read(&self, f: &mut dyn FnMut(&T)) {
+        self.storage.with(f)
+    }
+
+    fn modify(&self, f: &mut dyn FnMut(&mut T)) {
+        self.storage.modify(|val| {
+            f(val);
+            // queued while the value is locked, so the changes are sent in the order they're written
+            self.changes.lock().queue.push_back(val.clone());
+        });
+        self.send_changes();
+    }
+
+    fn stream(&self) -> Stream<T> {
+        self.sink.stream()
+    }
+
+    fn watch(&self) -> Arc<dyn Watch> {
+        self.storage.clone()
+    }
+}
+
+/// A var that projects a part of it's parent's value.
+struct Lens<P, G, S> {
+    parent: Var<P>,
+    get: Arc<G>,
+    set: S,
+}
+
+impl<P, T, G, S> VarSource<T> for Lens<P, G, S>
+where
+    P: 'static,
+    T: Clone + 'static,
+    G: Fn(&P) -> &T + Send + Sync + 'static,
+    S: Fn(&mut P, T) + Send + Sync + 'static,
+{
+    fn read(&self, f: &mut dyn FnMut(&T)) {
+        self.parent.source.read(&mut |p| f((self.get)(p)))
+    }
+
+    fn modify(&self, f: &mut dyn FnMut(&mut T)) {
+        // the parent is locked during the whole operation, so the write is atomic
+        self.parent.source.modify(&mut |p| {
+            let mut val = (self.get)(p).clone();
+            f(&mut val);
+            (self.set)(p, val);
+        })
+    }
+
+    fn stream(&self) -> Stream<T> {
+        let get = self.get.clone();
+        self.parent.source.stream().map(move |p| get(&p).clone())
+    }
+
+    fn watch(&self) -> Arc<dyn Watch> {
+        self.parent.source.watch()
+    }
+}
+
+/// A directly settable value that changes over time.
+pub struct Var<T> {
+    source: Arc<dyn VarSource<T>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Var<T> {
+    /// Creates a var with an initial value.
+    pub fn new(initial: T) -> Self {
+        Var {
+            source: Arc::new(Root {
+                storage: Arc::new(Storage::new(initial)),
+                sink: Sink::new(),
+                changes: Mutex::new(Changes {
+                    queue: VecDeque::new(),
+                    sending: false,
+                }),
+            }),
+        }
+    }
+}
+
+impl<T: Clone + 'static> Var<T> {
+    /// Creates a signal that reads the value of this var.
+    pub fn signal(&self) -> Signal<T> {
+        let this = self.clone();
+        Signal::from_fn_watch(move || this.sample(), Some(self.source.watch()))
+    }
+
+    /// Creates a stream that receives the new value every time this var is written.
+    ///
+    /// The values are sent in the same order they're written, so the last one is always the
+    /// current value. Only one thread sends at a time: a write done while another thread is sending
+    /// is left to that thread, and a write done from a callback of this stream is sent after the
+    /// current value reaches all the callbacks.
+    ///
+    /// On a var created by `Var::lens` this fires every time the parent is written, even if the
+    /// projected value didn't change.
+    #[inline]
+    pub fn stream(&self) -> Stream<T> {
+        self.source.stream()
+    }
+
+    /// Gets the current value.
+    pub fn sample(&self) -> T {
+        let mut res = None;
+        self.source.read(&mut |val| res = Some(val.clone()));
+        res.unwrap()
+    }
+
+    /// Sets the value.
+    pub fn set(&self, val: T) {
+        let mut val = Some(val);
+        self.source.modify(&mut |cur| *cur = val.take().unwrap());
+    }
+
+    /// Updates the value using the provided function.
+    ///
+    /// The old value is cloned before calling the closure, so if it panics the var will remain
+    /// unchanged.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(T) -> T,
+    {
+        let mut f = Some(f);
+        self.source
+            .modify(&mut |cur| *cur = f.take().unwrap()(cur.clone()));
+    }
+
+    /// Sets the value and returns the previous one.
+    pub fn replace(&self, val: T) -> T {
+        let mut val = Some(val);
+        let mut old = None;
+        self.source
+            .modify(&mut |cur| old = Some(mem::replace(cur, val.take().unwrap())));
+        old.unwrap()
+    }
+
+    /// Creates a var that reads and writes a part of this var's value.
+    ///
+    /// The `get` function projects the part from the whole value, and `set` stores a new part into
+    /// it. Writes on the resulting var update this var's value in a single atomic operation, so
+    /// they're seen by this var's signals and change stream.
+    ///
+    /// # Example
+    /// ```
+    /// use frappe::Var;
+    ///
+    /// #[derive(Clone)]
+    /// struct Form { name: String, age: u32 }
+    ///
+    /// let form = Var::new(Form { name: "".into(), age: 0 });
+    /// let name = form.lens(|f| &f.name, |f, v| f.name = v);
+    ///
+    /// name.set("Alice".into());
+    /// assert_eq!(form.sample().name, "Alice");
+    /// ```
+    pub fn lens<U, G, S>(&self, get: G, set: S) -> Var<U>
+    where
+        U: Clone + 'static,
+        G: Fn(&T) -> &U + Send + Sync + 'static,
+        S: Fn(&mut T, U) + Send + Sync + 'static,
+    {
+        Var {
+            source: Arc::new(Lens {
+                parent: self.clone(),
+                get: Arc::new(get),
+                set,
+            }),
+        }
+    }
+}
+
+impl<T> Clone for Var<T> {
+    /// Creates a copy of this var that references the same value.
+    fn clone(&self) -> Self {
+        Var {
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + Default + 'static> Default for Var<T> {
+    /// Creates a var with T's default value.
+    #[inline]
+    fn default() -> Self {
+        Var::new(T::default())
+    }
+}
+
+impl<T: Clone + 'static> From<Var<T>> for Signal<T> {
+    /// Creates a signal that reads the value of the var.
+    #[inline]
+    fn from(var: Var<T>) -> Self {
+        var.signal()
+    }
+}
+
+impl<T> fmt::Debug for Var<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Var(Source@{:p})", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_write() {
+        let var = Var::new(1);
+        let sig = var.signal();
+        let changes = var.stream().collect::<Vec<_>>();
+
+        var.set(2);
+        assert_eq!(sig.sample(), 2);
+        var.update(|x| x * 10);
+        assert_eq!(sig.sample(), 20);
+        assert_eq!(var.replace(7), 20);
+        assert_eq!(var.sample(), 7);
+        assert_eq!(changes.sample(), [2, 20, 7]);
+    }
+
+    #[test]
+    fn var_changes_order() {
+        let var = Var::new(0);
+        let last = var.stream().hold(0);
+        let var_ = var.clone();
+        // writes from a callback are sent after the current change
+        let seen = var
+            .stream()
+            .inspect(move |x| {
+                if *x == 1 {
+                    var_.set(2);
+                }
+            })
+            .collect::<Vec<_>>();
+
+        var.set(1);
+        assert_eq!(seen.sample(), [1, 2]);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let var = var.clone();
+                std::thread::spawn(move || (0..100).for_each(|j| var.set(i * 100 + j)))
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(last.sample(), var.sample());
+    }
+
+    #[test]
+    fn var_update_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let var = Var::new(vec![1, 2]);
+        let res = catch_unwind(AssertUnwindSafe(|| var.update(|_| panic!("oops"))));
+        assert!(res.is_err());
+        assert_eq!(var.sample(), [1, 2]);
+    }
+
+    #[test]
+    fn var_lens() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Form {
+            name: String,
+            pos: (i32, i32),
+        }
+
+        let form = Var::new(Form {
+            name: "a".into(),
+            pos: (1, 2),
+        });
+        let name = form.lens(|f| &f.name, |f, v| f.name = v);
+        let pos = form.lens(|f| &f.pos, |f, v| f.pos = v);
+        let x = pos.lens(|p| &p.0, |p, v| p.0 = v);
+        let name_sig = name.signal();
+        let form_changes = form.stream().collect::<Vec<_>>();
+        let x_changes = x.stream().collect::<Vec<_>>();
+
+        name.set("b".into());
+        assert_eq!(name_sig.sample(), "b");
+        assert_eq!(form.sample().name, "b");
+
+        x.update(|x| x + 10);
+        assert_eq!(pos.sample(), (11, 2));
+        assert_eq!(
+            form.sample(),
+            Form {
+                name: "b".into(),
+                pos: (11, 2)
+            }
+        );
+
+        form.set(Form {
+            name: "c".into(),
+            pos: (0, 0),
+        });
+        assert_eq!(name_sig.sample(), "c");
+        assert_eq!(x.sample(), 0);
+        assert_eq!(form_changes.sample().len(), 3);
+        assert_eq!(x_changes.sample(), [1, 11, 0]);
+    }
+}